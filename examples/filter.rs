@@ -1,10 +1,9 @@
-use prominence::image::io::Reader as ImageReader;
-
 const BLACK_MAX_LIGHTNESS: f32 = 0.02;
 const WHITE_MIN_LIGHTNESS: f32 = 0.90;
 
 // this filter uses the same approach as the default filter in prominence, except it allows more
-// darker colors and blocks more lighter colors
+// darker colors and blocks more lighter colors. the same thresholds can also be used with the
+// default filter through prominence::DefaultFilter::with_thresholds
 struct CustomFilter;
 impl prominence::Filter for CustomFilter {
     fn is_allowed(&self, _: (u8, u8, u8), (_, _, l): (f32, f32, f32)) -> bool {
@@ -21,11 +20,8 @@ fn is_white(l: f32) -> bool {
 }
 
 fn main() {
-    let reader = ImageReader::open("ab67616d0000b2732cd7888600aafe2eb8b6be9f.jpg").unwrap();
-    let img = reader.decode().unwrap();
-    let buf = img.to_rgb8();
-
-    let palette = prominence::PaletteBuilder::from_image(buf)
+    let palette = prominence::PaletteBuilder::open("ab67616d0000b2732cd7888600aafe2eb8b6be9f.jpg")
+        .unwrap()
         .clear_filters() // remove the default filter
         .add_filter(CustomFilter) // add our custom filter
         .generate();
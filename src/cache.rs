@@ -0,0 +1,180 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem::discriminant,
+};
+
+use crate::{Palette, PaletteBuilder};
+
+/// A cache of generated palettes, keyed by the image they were generated from along with the
+/// builder's settings.
+///
+/// Generating a palette through the cache returns the previously generated palette if the same
+/// image was already generated with the same settings, skipping the generation entirely. This is
+/// useful for applications that revisit the same images.
+///
+/// The cache key is a hash of the image's pixels and dimensions and the builder's settings, such as
+/// the resize area, maximum color count, targets and filters. Custom filters and score functions
+/// can't be compared, so only their amount and presence are part of the key. Builders with
+/// different custom filters or score functions should use separate caches.
+#[derive(Debug, Default)]
+pub struct PaletteCache {
+    palettes: HashMap<u64, Palette>,
+}
+
+impl PaletteCache {
+    /// Returns a new, empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consume the builder and return the cached palette for its image and settings, or generate
+    /// a new palette and cache it if there is none.
+    pub fn generate<P>(&mut self, builder: PaletteBuilder<P>) -> Palette
+    where
+        P: image::Pixel<Subpixel = u8> + 'static + std::cmp::Eq + std::hash::Hash,
+    {
+        let key = cache_key(&builder);
+        self.palettes
+            .entry(key)
+            .or_insert_with(|| builder.generate())
+            .clone()
+    }
+
+    /// Returns the amount of cached palettes.
+    pub fn len(&self) -> usize {
+        self.palettes.len()
+    }
+
+    /// Returns whether the cache has no palettes.
+    pub fn is_empty(&self) -> bool {
+        self.palettes.is_empty()
+    }
+
+    /// Remove every cached palette.
+    pub fn clear(&mut self) {
+        self.palettes.clear();
+    }
+}
+
+/// Returns a hash of a builder's image and settings.
+fn cache_key<P>(builder: &PaletteBuilder<P>) -> u64
+where
+    P: image::Pixel<Subpixel = u8> + 'static + std::cmp::Eq + std::hash::Hash,
+{
+    let mut hasher = DefaultHasher::new();
+
+    P::COLOR_MODEL.hash(&mut hasher);
+    builder.image.dimensions().hash(&mut hasher);
+    builder.image.as_raw().hash(&mut hasher);
+    builder.weighted_colors.hash(&mut hasher);
+
+    builder.maximum_color_count.hash(&mut hasher);
+    builder.resize_area.hash(&mut hasher);
+    builder
+        .region
+        .map(|region| (region.x, region.y, region.width, region.height))
+        .hash(&mut hasher);
+
+    for target in builder.targets.iter().copied() {
+        target.id().hash(&mut hasher);
+        [
+            target.minimum_saturation(),
+            target.target_saturation(),
+            target.maximum_saturation(),
+            target.minimum_lightness(),
+            target.target_lightness(),
+            target.maximum_lightness(),
+            target.saturation_weight(),
+            target.lightness_weight(),
+            target.population_weight(),
+        ]
+        .map(f32::to_bits)
+        .hash(&mut hasher);
+        target.is_exclusive().hash(&mut hasher);
+        discriminant(&target.space()).hash(&mut hasher);
+    }
+
+    builder.use_default_filter.hash(&mut hasher);
+    builder.minimum_saturation.map(f32::to_bits).hash(&mut hasher);
+    builder.filters.len().hash(&mut hasher);
+    builder.grayscale.hash(&mut hasher);
+
+    let options = &builder.quantizer_options;
+    discriminant(&options.kind).hash(&mut hasher);
+    options.linear_averaging.hash(&mut hasher);
+    options.refine_iterations.hash(&mut hasher);
+    options.deterministic.hash(&mut hasher);
+    discriminant(&options.volume_weighting).hash(&mut hasher);
+    discriminant(&options.split_priority).hash(&mut hasher);
+    discriminant(&options.split_method).hash(&mut hasher);
+    options.weight_by_alpha.hash(&mut hasher);
+    options.saturation_boost.to_bits().hash(&mut hasher);
+    discriminant(&options.representative).hash(&mut hasher);
+
+    builder.minimum_population_fraction.to_bits().hash(&mut hasher);
+    builder.minimum_swatch_population.hash(&mut hasher);
+    builder.keep_rejected_swatches.hash(&mut hasher);
+    builder.exclude_background.hash(&mut hasher);
+    builder.premultiplied.hash(&mut hasher);
+    builder
+        .merge_hsl
+        .map(|(hue, lightness)| (hue.to_bits(), lightness.to_bits()))
+        .hash(&mut hasher);
+    builder.sample_stride.hash(&mut hasher);
+    builder.sample_count.hash(&mut hasher);
+    builder.sample_seed.hash(&mut hasher);
+
+    let scoring = &builder.scoring;
+    scoring.score_fn.is_some().hash(&mut hasher);
+    discriminant(&scoring.population_scale).hash(&mut hasher);
+    discriminant(&scoring.population_reference).hash(&mut hasher);
+    discriminant(&scoring.fallback).hash(&mut hasher);
+    scoring.relax_steps.hash(&mut hasher);
+    scoring.relax_step_size.to_bits().hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn identical_inputs_hit_the_cache() {
+        let image = RgbImage::from_fn(32, 32, |x, y| Rgb([x as u8 * 8, y as u8 * 8, 128]));
+        let mut cache = PaletteCache::new();
+
+        let first = cache.generate(PaletteBuilder::from_image(image.clone()));
+        let second = cache.generate(PaletteBuilder::from_image(image.clone()));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(first, second);
+
+        // different settings miss the cache
+        cache.generate(PaletteBuilder::from_image(image).maximum_color_count(4));
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn different_minimum_saturations_miss_the_cache() {
+        let image = RgbImage::from_fn(32, 32, |x, y| Rgb([x as u8 * 8, y as u8 * 8, 128]));
+        let builder = |minimum_saturation| {
+            PaletteBuilder::from_image(image.clone()).minimum_saturation(minimum_saturation)
+        };
+        let mut cache = PaletteCache::new();
+
+        let low = cache.generate(builder(0.2));
+        let high = cache.generate(builder(0.5));
+        assert_eq!(cache.len(), 2);
+        assert_ne!(low, high);
+        assert_eq!(high, builder(0.5).generate());
+
+        cache.generate(builder(0.2));
+        assert_eq!(cache.len(), 2);
+    }
+}
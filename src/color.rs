@@ -0,0 +1,435 @@
+#[cfg(feature = "palette")]
+use palette::{FromColor, Lab, LinSrgb, Oklab, Oklch, Srgb, Yxy};
+
+// the matrices converting between linear sRGB and the LMS cone responses OKLab is built on, and
+// between the cube roots of the responses and OKLab, from Björn Ottosson's reference
+#[cfg(not(feature = "palette"))]
+const LINEAR_SRGB_TO_LMS: [[f32; 3]; 3] = [
+    [0.41222146, 0.53633255, 0.051445995],
+    [0.2119035, 0.6806995, 0.10739696],
+    [0.08830246, 0.28171885, 0.6299787],
+];
+#[cfg(not(feature = "palette"))]
+const LMS_TO_OKLAB: [[f32; 3]; 3] = [
+    [0.21045426, 0.7936178, -0.004072047],
+    [1.9779985, -2.4285922, 0.4505937],
+    [0.025904037, 0.78277177, -0.80867577],
+];
+#[cfg(not(feature = "palette"))]
+const OKLAB_TO_LMS: [[f32; 3]; 3] = [
+    [1.0, 0.39633778, 0.21580376],
+    [1.0, -0.105561346, -0.06385417],
+    [1.0, -0.08948418, -1.2914855],
+];
+#[cfg(not(feature = "palette"))]
+const LMS_TO_LINEAR_SRGB: [[f32; 3]; 3] = [
+    [4.0767417, -3.3077116, 0.23096994],
+    [-1.268438, 2.6097574, -0.34131938],
+    [-0.0041960863, -0.7034186, 1.7076147],
+];
+
+// the matrix converting linear sRGB into CIE XYZ, and the XYZ of the D65 white point
+#[cfg(not(feature = "palette"))]
+const LINEAR_SRGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.119192, 0.9503041],
+];
+#[cfg(not(feature = "palette"))]
+const D65_WHITE_POINT: [f32; 3] = [0.95047, 1.0, 1.08883];
+
+/// Convert an 8-bit sRGB channel value into linear light in the range 0 to 1.
+pub(crate) fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear light value in the range 0 to 1 into an 8-bit sRGB channel value.
+pub(crate) fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (value * 255.0).round() as u8
+}
+
+/// Returns the relative luminance of an 8-bit sRGB color as defined by WCAG, in the range 0 to 1.
+pub(crate) fn relative_luminance((r, g, b): (u8, u8, u8)) -> f32 {
+    0.2126 * srgb_to_linear(r) + 0.7152 * srgb_to_linear(g) + 0.0722 * srgb_to_linear(b)
+}
+
+/// An operator that maps linear high dynamic range values onto the 0 to 1 range before they're
+/// encoded into 8-bit sRGB. Each channel is mapped independently.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ToneMapping {
+    /// Clamp values above 1 to 1, losing any detail in the highlights.
+    Clamp,
+    /// The Reinhard operator, `x / (1 + x)`, which compresses highlights smoothly but darkens the
+    /// whole image somewhat.
+    #[default]
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, which keeps more contrast in the midtones than
+    /// Reinhard.
+    Aces,
+    /// Multiply the values by the given exposure before clamping them.
+    Exposure(f32),
+}
+
+impl ToneMapping {
+    /// Map a linear HDR value onto the 0 to 1 range. Negative and NaN values map to 0.
+    pub(crate) fn map(self, value: f32) -> f32 {
+        let value = value.max(0.0);
+        let mapped = match self {
+            ToneMapping::Clamp => value,
+            ToneMapping::Reinhard => value / (1.0 + value),
+            ToneMapping::Aces => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+            ToneMapping::Exposure(exposure) => value * exposure,
+        };
+
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+/// A type of color vision deficiency to simulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorBlindness {
+    /// The absence of red-sensitive cones.
+    Protanopia,
+    /// The absence of green-sensitive cones, the most common form of color blindness.
+    Deuteranopia,
+    /// The absence of blue-sensitive cones.
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    /// Returns the matrix simulating this deficiency in linear RGB. The matrices are from Machado,
+    /// Oliveira and Fernandes (2009) at full severity, derived from a model of how the cone
+    /// responses in LMS space are affected by the deficiency.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            ColorBlindness::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorBlindness::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorBlindness::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+/// Simulate how an 8-bit sRGB color looks with a given color vision deficiency.
+pub(crate) fn simulate_color_blindness(
+    (r, g, b): (u8, u8, u8),
+    kind: ColorBlindness,
+) -> (u8, u8, u8) {
+    let linear = [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)];
+    let simulated = kind
+        .matrix()
+        .map(|row| row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]);
+
+    (
+        linear_to_srgb(simulated[0]),
+        linear_to_srgb(simulated[1]),
+        linear_to_srgb(simulated[2]),
+    )
+}
+
+/// Convert an 8-bit sRGB color into OKLab.
+#[cfg(feature = "palette")]
+pub(crate) fn rgb_to_oklab((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let oklab = Oklab::from_color(Srgb::new(r, g, b).into_linear::<f32>());
+    (oklab.l, oklab.a, oklab.b)
+}
+
+/// Convert an 8-bit sRGB color into OKLab.
+#[cfg(not(feature = "palette"))]
+pub(crate) fn rgb_to_oklab(rgb: (u8, u8, u8)) -> (f32, f32, f32) {
+    let lms = multiply(LINEAR_SRGB_TO_LMS, linearize(rgb)).map(f32::cbrt);
+    let [l, a, b] = multiply(LMS_TO_OKLAB, lms);
+    (l, a, b)
+}
+
+/// Convert an OKLab color into 8-bit sRGB, clamping it into the sRGB gamut.
+#[cfg(feature = "palette")]
+pub(crate) fn oklab_to_rgb((l, a, b): (f32, f32, f32)) -> (u8, u8, u8) {
+    let linear = LinSrgb::from_color(Oklab::new(l, a, b));
+    (
+        linear_to_srgb(linear.red),
+        linear_to_srgb(linear.green),
+        linear_to_srgb(linear.blue),
+    )
+}
+
+/// Convert an OKLab color into 8-bit sRGB, clamping it into the sRGB gamut.
+#[cfg(not(feature = "palette"))]
+pub(crate) fn oklab_to_rgb((l, a, b): (f32, f32, f32)) -> (u8, u8, u8) {
+    let lms = multiply(OKLAB_TO_LMS, [l, a, b]).map(|value| value.powi(3));
+    let [r, g, b] = multiply(LMS_TO_LINEAR_SRGB, lms).map(linear_to_srgb);
+    (r, g, b)
+}
+
+/// Convert an 8-bit sRGB color into OKLch, returning the hue in degrees.
+#[cfg(feature = "palette")]
+pub(crate) fn rgb_to_oklch((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let oklch = Oklch::from_color(Srgb::new(r, g, b).into_linear::<f32>());
+    (oklch.l, oklch.chroma, oklch.hue.into_positive_degrees())
+}
+
+/// Convert an 8-bit sRGB color into OKLch, returning the hue in degrees.
+#[cfg(not(feature = "palette"))]
+pub(crate) fn rgb_to_oklch(rgb: (u8, u8, u8)) -> (f32, f32, f32) {
+    let (l, a, b) = rgb_to_oklab(rgb);
+    (l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0))
+}
+
+/// Convert an 8-bit sRGB color into CIELAB with a D65 white point.
+#[cfg(feature = "palette")]
+pub(crate) fn rgb_to_lab((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let lab = Lab::from_color(Srgb::new(r, g, b).into_linear::<f32>());
+    (lab.l, lab.a, lab.b)
+}
+
+/// Convert an 8-bit sRGB color into CIELAB with a D65 white point.
+#[cfg(not(feature = "palette"))]
+pub(crate) fn rgb_to_lab(rgb: (u8, u8, u8)) -> (f32, f32, f32) {
+    const EPSILON: f32 = 216.0 / 24389.0;
+    const KAPPA: f32 = 24389.0 / 27.0;
+
+    let xyz = multiply(LINEAR_SRGB_TO_XYZ, linearize(rgb));
+    let [fx, fy, fz] = [0, 1, 2].map(|i| {
+        let value = xyz[i] / D65_WHITE_POINT[i];
+        if value > EPSILON {
+            value.cbrt()
+        } else {
+            (KAPPA * value + 16.0) / 116.0
+        }
+    });
+
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Convert an 8-bit sRGB color into CIE xyY, returning the (x, y) chromaticity and the luminance.
+#[cfg(feature = "palette")]
+fn rgb_to_yxy((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let yxy = Yxy::from_color(Srgb::new(r, g, b).into_linear::<f32>());
+    (yxy.x, yxy.y, yxy.luma)
+}
+
+/// Convert an 8-bit sRGB color into CIE xyY, returning the (x, y) chromaticity and the luminance.
+/// Black has no chromaticity, so it's returned as all zeroes.
+#[cfg(not(feature = "palette"))]
+fn rgb_to_yxy(rgb: (u8, u8, u8)) -> (f32, f32, f32) {
+    let [x, y, z] = multiply(LINEAR_SRGB_TO_XYZ, linearize(rgb));
+    let sum = x + y + z;
+
+    if sum <= 0.0 {
+        return (0.0, 0.0, 0.0);
+    }
+
+    (x / sum, y / sum, y)
+}
+
+/// Convert an 8-bit sRGB color into linear sRGB channels.
+#[cfg(not(feature = "palette"))]
+fn linearize((r, g, b): (u8, u8, u8)) -> [f32; 3] {
+    [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)]
+}
+
+/// Multiply a vector by a 3x3 matrix.
+#[cfg(not(feature = "palette"))]
+fn multiply(matrix: [[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+// the furthest a color may be from the Planckian locus in the CIE 1960 UCS for its correlated color
+// temperature to be meaningful, and the temperature range the locus approximation is valid in
+const MAX_PLANCKIAN_DISTANCE: f32 = 0.05;
+const PLANCKIAN_TEMPERATURE_RANGE: (f32, f32) = (1000.0, 15000.0);
+
+/// Estimate the correlated color temperature of an 8-bit sRGB color in kelvin with McCamy's
+/// approximation. Returns `None` for black and for colors too far from the Planckian locus.
+pub(crate) fn correlated_color_temperature((r, g, b): (u8, u8, u8)) -> Option<f32> {
+    let (x, y, luma) = rgb_to_yxy((r, g, b));
+    if luma <= 0.0 {
+        return None;
+    }
+
+    let n = (x - 0.3320) / (0.1858 - y);
+    let temperature = 449.0 * n.powi(3) + 3525.0 * n.powi(2) + 6823.3 * n + 5520.33;
+
+    let (min_temperature, max_temperature) = PLANCKIAN_TEMPERATURE_RANGE;
+    if !(min_temperature..=max_temperature).contains(&temperature) {
+        return None;
+    }
+
+    // the color in the CIE 1960 UCS, and the point on the Planckian locus at the temperature with
+    // Krystek's approximation
+    let denominator = -2.0 * x + 12.0 * y + 3.0;
+    let (u, v) = (4.0 * x / denominator, 6.0 * y / denominator);
+
+    let t = temperature as f64;
+    let locus_u = (0.860117757 + 1.54118254e-4 * t + 1.28641212e-7 * t * t)
+        / (1.0 + 8.42420235e-4 * t + 7.08145163e-7 * t * t);
+    let locus_v = (0.317398726 + 4.22806245e-5 * t + 4.20481691e-8 * t * t)
+        / (1.0 - 2.89741816e-5 * t + 1.61456053e-7 * t * t);
+
+    let distance = (u - locus_u as f32).hypot(v - locus_v as f32);
+    (distance <= MAX_PLANCKIAN_DISTANCE).then_some(temperature)
+}
+
+/// Returns the Euclidean distance between two CIELAB colors, which is the CIE76 color difference.
+pub(crate) fn lab_distance((l1, a1, b1): (f32, f32, f32), (l2, a2, b2): (f32, f32, f32)) -> f32 {
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+/// An error from parsing a hex color string, such as from [`parse_hex_palette`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    index: usize,
+    value: String,
+}
+
+impl ParseError {
+    /// Returns the index of the malformed string in the parsed list.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the malformed string.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid hex color at index {}: {:?}", self.index, self.value)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a list of hex color strings into 8-bit sRGB colors.
+///
+/// Each string may be in the `#rrggbb` or `rrggbb` long form, or the `#rgb` short form where each
+/// digit is repeated, so `#f80` is `#ff8800`. The digits are case-insensitive. If any string is
+/// malformed, an error with its index is returned.
+pub fn parse_hex_palette(hexes: &[&str]) -> Result<Vec<(u8, u8, u8)>, ParseError> {
+    hexes
+        .iter()
+        .enumerate()
+        .map(|(index, hex)| {
+            parse_hex(hex).ok_or_else(|| ParseError {
+                index,
+                value: hex.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse a `#rrggbb`, `rrggbb` or `#rgb` hex string into an 8-bit sRGB color.
+pub(crate) fn parse_hex(hex: &str) -> Option<(u8, u8, u8)> {
+    let (short, digits) = match hex.strip_prefix('#') {
+        Some(digits) => (digits.len() == 3, digits),
+        None => (false, hex),
+    };
+
+    if !digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    if short {
+        let channel = |i: usize| channel(&digits[i..i + 1]).map(|digit| digit * 0x11);
+        Some((channel(0)?, channel(1)?, channel(2)?))
+    } else if digits.len() == 6 {
+        Some((channel(&digits[0..2])?, channel(&digits[2..4])?, channel(&digits[4..6])?))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_palettes_in_every_form() {
+        let orange = (0xff, 0x88, 0x00);
+
+        assert_eq!(parse_hex_palette(&["#ff8800", "#FF8800"]), Ok(vec![orange, orange]));
+        assert_eq!(parse_hex_palette(&["ff8800", "#f80"]), Ok(vec![orange, orange]));
+        assert_eq!(parse_hex_palette(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn malformed_hex_colors_are_errors() {
+        for malformed in ["#ff880", "f80", "#gg8800", "", "#", "#ff88000"] {
+            let err = parse_hex_palette(&["#000000", malformed]).unwrap_err();
+
+            assert_eq!(err.index(), 1);
+            assert_eq!(err.value(), malformed);
+        }
+    }
+
+    const SAMPLE_COLORS: [(u8, u8, u8); 6] = [
+        (255, 0, 0),
+        (0, 255, 0),
+        (51, 102, 153),
+        (200, 40, 160),
+        (128, 128, 128),
+        (250, 240, 10),
+    ];
+
+    #[cfg(feature = "palette")]
+    #[test]
+    fn built_in_hsl_matches_palette() {
+        use palette::{Hsl, RgbHue};
+
+        for (r, g, b) in SAMPLE_COLORS {
+            let (h, s, l) = crate::rgb_to_hsl((r, g, b));
+            let hsl = Hsl::from_color(Srgb::new(r, g, b).into_format::<f32>());
+
+            let hue_difference = (RgbHue::from_degrees(h) - hsl.hue).into_degrees();
+            assert!(s == 0.0 || hue_difference.abs() < 0.01, "{:?}", (r, g, b));
+            assert!((s - hsl.saturation).abs() < 0.001, "{:?}", (r, g, b));
+            assert!((l - hsl.lightness).abs() < 0.001, "{:?}", (r, g, b));
+        }
+    }
+
+    // both the palette and the built-in conversions have to give Ottosson's reference values
+    #[test]
+    fn oklab_matches_the_reference() {
+        let close = |(l, a, b): (f32, f32, f32), (ref_l, ref_a, ref_b): (f32, f32, f32)| {
+            (l - ref_l).abs() < 0.001 && (a - ref_a).abs() < 0.001 && (b - ref_b).abs() < 0.001
+        };
+
+        assert!(close(rgb_to_oklab((255, 255, 255)), (1.0, 0.0, 0.0)));
+        assert!(close(rgb_to_oklab((255, 0, 0)), (0.62796, 0.22486, 0.12585)));
+        assert!(close(rgb_to_oklab((0, 0, 255)), (0.45201, -0.03246, -0.31153)));
+        for rgb in SAMPLE_COLORS {
+            assert_eq!(oklab_to_rgb(rgb_to_oklab(rgb)), rgb);
+        }
+    }
+}
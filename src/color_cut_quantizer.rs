@@ -1,28 +1,185 @@
-use std::collections::{BinaryHeap, HashMap};
+use std::{
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    time::Instant,
+};
 
-use crate::{filter::Filter, swatch::Swatch};
+use crate::{
+    color,
+    filter::{FilterStage, StagedFilter},
+    pixel::QuantizablePixel,
+    swatch::Swatch,
+    QuantizationStats, Timings,
+};
 
 const QUANTIZE_WORD_WIDTH: u32 = 5;
 const QUANTIZE_WORD_MAX: u8 = (1 << QUANTIZE_WORD_WIDTH) - 1;
 
+// the luma coefficients of the red, green and blue channels
+const LUMA_WEIGHTS: (f32, f32, f32) = (0.299, 0.587, 0.114);
+
+// the ranges of the OKLab components for colors within the sRGB gamut
+const OKLAB_L_RANGE: (f32, f32) = (0.0, 1.0);
+const OKLAB_A_RANGE: (f32, f32) = (-0.24, 0.28);
+const OKLAB_B_RANGE: (f32, f32) = (-0.32, 0.2);
+
+// the amount of colors a Vbox has to have for its colors to be sorted in parallel, or for it to be
+// split in parallel
+#[cfg(feature = "rayon")]
+const PARALLEL_SORT_THRESHOLD: usize = 4096;
+// the amount of Vboxes to split in parallel for each thread, so threads that finish early have more
+// boxes to pick up
+#[cfg(feature = "rayon")]
+const PARALLEL_SPLIT_BOXES_PER_THREAD: usize = 4;
+
 pub struct ColorCutQuantizer<P>
 where
-    P: image::Pixel<Subpixel = u8>,
+    P: QuantizablePixel,
 {
     pixels: Vec<P>,
+    // precomputed colors and their counts, quantized along with the pixels
+    weighted_colors: Vec<((u8, u8, u8), u32)>,
     max_colors: usize,
-    filters: Vec<Box<dyn Filter>>,
+    filters: Vec<StagedFilter>,
+    options: QuantizerOptions,
 }
 
-struct Vbox<'a, P>
-where
-    P: image::Pixel<Subpixel = u8> + std::cmp::Eq + std::hash::Hash,
-{
-    colors: &'a mut [(P, u32)],
-    population: u32,
+/// Options controlling how the quantizer processes the image colors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuantizerOptions {
+    /// The color space the colors are quantized in.
+    pub kind: QuantizerKind,
+    /// Average the colors in each Vbox in linear light instead of directly in sRGB.
+    pub linear_averaging: bool,
+    /// The amount of k-means refinement iterations to run over the swatches after quantization.
+    pub refine_iterations: u32,
+    /// Build the color histogram in a deterministic order.
+    pub deterministic: bool,
+    /// How the color channels are weighted when choosing which Vbox to split and along which axis.
+    pub volume_weighting: VolumeWeighting,
+    /// Which Vboxes are split first.
+    pub split_priority: SplitPriority,
+    /// Where Vboxes are split.
+    pub split_method: SplitMethod,
+    /// Weight each pixel's count in the histogram by its alpha.
+    pub weight_by_alpha: bool,
+    /// How the representative color of each Vbox is chosen.
+    pub representative: Representative,
+    /// The factor of a color's HSL saturation its count in the histogram is boosted by before
+    /// quantizing.
+    pub saturation_boost: f32,
+}
+
+/// The color space an image's colors are quantized in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuantizerKind {
+    /// Quantize the colors in sRGB, splitting color boxes along the red, green and blue axes.
+    #[default]
+    Rgb,
+    /// Quantize the colors in OKLab, splitting color boxes along the perceptual lightness and the
+    /// green-red and blue-yellow axes. This tends to separate colors the eye distinguishes better
+    /// than quantizing in sRGB.
+    OkLab,
+}
+
+/// How the red, green and blue channel ranges of a color box are weighted when quantizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VolumeWeighting {
+    /// Weight every channel equally.
+    #[default]
+    Uniform,
+    /// Weight the channels by their luma coefficients, so spread in green matters the most and
+    /// spread in blue the least, like it does to the eye.
+    Luma,
+}
+
+/// The order color boxes are split in when quantizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitPriority {
+    /// Split the box with the largest volume first.
+    #[default]
+    Volume,
+    /// Split the box with the largest product of volume and population first. This favors
+    /// splitting small but densely populated boxes, preserving more detail in the image's most
+    /// common colors.
+    VolumeTimesPopulation,
+}
+
+/// Where color boxes are split along their longest dimension when quantizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitMethod {
+    /// Split the box at the population median, so both halves have roughly the same population.
+    #[default]
+    PopulationMedian,
+    /// Split the box at the midpoint of its longest dimension, so both halves span roughly the
+    /// same range of colors regardless of how the population is distributed.
+    DimensionMidpoint,
+}
+
+/// How the representative color of each color box is chosen when quantizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Representative {
+    /// The population-weighted mean of the colors in the box. The mean may fall between the
+    /// box's colors, so it isn't necessarily a color in the image.
+    #[default]
+    Mean,
+    /// The color at the population median of the box along its longest dimension. The median is
+    /// always one of the box's colors, so it's a color in the image up to quantization.
+    Median,
+}
+
+/// A color quantized into the quantizer's color space, with each component being the quantization
+/// word width long. Depending on the quantizer kind, the components are either red, green and blue,
+/// or OKLab lightness, a and b.
+type QuantizedColor = (u8, u8, u8);
+
+struct Vbox<'a> {
+    colors: &'a mut [(QuantizedColor, u32)],
+    options: QuantizerOptions,
+    // the total population is kept wide, since the counts of many colors may add up past u32
+    population: u64,
     red_range: (u8, u8),
     green_range: (u8, u8),
     blue_range: (u8, u8),
+    // the smallest packed color in the Vbox, which tells apart Vboxes with the same priority
+    min_color: u32,
+}
+
+/// The order Vboxes are split in, by their priority and then by their smallest packed color. Since
+/// no two Vboxes share a color, no two Vboxes in a queue have the same key, so the order they're
+/// split in doesn't depend on the order they were pushed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct VboxKey {
+    priority: f32,
+    min_color: u32,
+}
+
+/// A priority queue of Vboxes, popping the Vbox to split next first, along with the Vboxes that
+/// have been popped off the queue but cannot be split.
+struct SplitQueue<'a> {
+    queue: BinaryHeap<Vbox<'a>>,
+    unsplittable: Vec<Vbox<'a>>,
+    // whether splitting stopped at a Vbox that cannot be split
+    stopped: bool,
+}
+
+/// A Vbox popped off a [`SplitQueue`], by its key.
+#[derive(Debug, Clone, Copy)]
+// the keys are only needed when splitting in parallel
+#[cfg_attr(not(feature = "rayon"), allow(dead_code))]
+enum SplitStep {
+    /// The Vbox was split in two.
+    Split(VboxKey),
+    /// The Vbox cannot be split.
+    Unsplittable(VboxKey),
+}
+
+/// A Vbox split in parallel with other Vboxes, along with the steps taken splitting it, of which
+/// the first `consumed` are known to be taken when splitting serially.
+#[cfg(feature = "rayon")]
+struct Subtree<'a> {
+    queue: SplitQueue<'a>,
+    steps: Vec<SplitStep>,
+    consumed: usize,
 }
 
 enum Component {
@@ -33,147 +190,485 @@ enum Component {
 
 impl<P> ColorCutQuantizer<P>
 where
-    P: image::Pixel<Subpixel = u8> + std::cmp::Eq + std::hash::Hash,
+    P: QuantizablePixel,
 {
-    pub fn new(pixels: Vec<P>, max_colors: usize, filters: Vec<Box<dyn Filter>>) -> Self {
+    pub fn new(
+        pixels: Vec<P>,
+        max_colors: usize,
+        filters: Vec<StagedFilter>,
+        options: QuantizerOptions,
+    ) -> Self {
         Self {
             pixels,
+            weighted_colors: Vec::new(),
             max_colors,
             filters,
+            options,
         }
     }
 
-    pub fn get_quantized_colors(self) -> Vec<Swatch> {
-        // begin by generating a histogram of quantized pixel values
-        let mut hist = HashMap::new();
-        for pixel in self.pixels.iter() {
-            let pixel = pixel.map(|channel| modify_width(channel, 8, QUANTIZE_WORD_WIDTH));
-            *hist.entry(pixel).or_insert(0) += 1;
+    /// Add precomputed colors and their counts to quantize along with the pixels.
+    pub fn with_weighted_colors(self, weighted_colors: Vec<((u8, u8, u8), u32)>) -> Self {
+        Self {
+            weighted_colors,
+            ..self
         }
+    }
 
-        // convert the histogram into a collection of (color, count) tuples, filtering out unwanted
-        // colors
-        let hist_len = hist.len();
-        let mut colors: Vec<_> = hist
-            .into_iter()
-            .filter_map(|(pixel, count)| {
-                self.should_allow_color(pixel_to_rgb(&pixel))
-                    .then_some((pixel, count))
+    /// Returns every color to quantize along with its weight, one for each pixel in addition to
+    /// the weighted colors. When weighting by alpha, each pixel weighs its alpha value and each
+    /// weighted color weighs that of a fully opaque pixel per count.
+    fn weighted_rgb(&self) -> impl Iterator<Item = ((u8, u8, u8), u64)> + '_ {
+        let weight_by_alpha = self.options.weight_by_alpha;
+        let opaque_weight = if weight_by_alpha { u8::MAX as u64 } else { 1 };
+
+        self.pixels
+            .iter()
+            .map(move |pixel| {
+                let weight = if weight_by_alpha {
+                    pixel.alpha() as u64
+                } else {
+                    1
+                };
+                (pixel.rgb(), weight)
             })
-            .collect();
+            .chain(
+                self.weighted_colors
+                    .iter()
+                    .map(move |&(rgb, count)| (rgb, count as u64 * opaque_weight)),
+            )
+    }
 
-        // the colors have to be ordered at this point, so order them by combining their channels
-        // into a single RGB integer where each channel is the quantization word width long
-        colors.sort_by_key(|(pixel, _)| {
-            let (r, g, b) = pixel_to_rgb(pixel);
-            ((r as u32) << (QUANTIZE_WORD_WIDTH * 2))
-                | ((g as u32) << QUANTIZE_WORD_WIDTH)
-                | b as u32
-        });
+    /// Convert a histogram weight from [`ColorCutQuantizer::weighted_rgb`] into a pixel count.
+    fn weight_to_count(&self, weight: u64) -> u32 {
+        let count = if self.options.weight_by_alpha {
+            (weight + u8::MAX as u64 / 2) / u8::MAX as u64
+        } else {
+            weight
+        };
+
+        count.min(u32::MAX as u64) as u32
+    }
+
+    /// Returns the filtered histogram of quantized colors, with each color converted back into
+    /// 8-bit sRGB.
+    pub fn histogram(&self) -> Vec<((u8, u8, u8), u32)> {
+        let (_, colors) = self.build_histogram();
+        colors
+            .into_iter()
+            .map(|(color, count)| (self.options.kind.approximate_rgb(color), count))
+            .collect()
+    }
+
+    /// Quantize the pixels into swatches, recording how long building the histogram and splitting
+    /// it took along with statistics about the quantization. Returns the swatches allowed by the
+    /// filters along with the swatches the filters rejected.
+    pub fn get_quantized_colors(
+        self,
+        timings: &mut Timings,
+        stats: &mut QuantizationStats,
+    ) -> (Vec<Swatch>, Vec<Swatch>) {
+        let start = Instant::now();
+        let (hist_len, mut colors) = self.build_histogram();
+        if self.options.saturation_boost != 0.0 {
+            self.boost_saturated_colors(&mut colors);
+        }
+        timings.histogram = start.elapsed();
+
+        stats.requested_colors = self.max_colors;
+        stats.histogram_colors = hist_len;
+
+        let start = Instant::now();
+        let swatches = if colors.len() <= self.max_colors {
+            // there are less colors left after filtering than requested, no need for further
+            // processing; just return each color as a swatch. this includes the filters having
+            // rejected every color, in which case there are no swatches at all
+            stats.quantized_colors = colors.len();
+            stats.terminated_early = colors.len() < self.max_colors;
 
-        if hist_len <= self.max_colors {
-            // there are less colors than requested, no need for further processing; just return
-            // each color as a swatch
             colors
                 .into_iter()
-                .map(|(pixel, count)| Swatch::new(pixel_to_rgb(&pixel), count))
+                .map(|(color, count)| Swatch::new(self.options.kind.approximate_rgb(color), count))
                 .collect()
         } else {
-            self.quantize_pixels(colors)
+            self.quantize_pixels(colors, stats)
+        };
+
+        // separate out unwanted colors
+        let swatches = swatches
+            .into_iter()
+            .partition(|swatch| self.should_allow_color(FilterStage::Output, swatch.rgb()));
+        timings.split = start.elapsed();
+
+        swatches
+    }
+
+    /// Build a histogram of the quantized pixel values, returning the amount of distinct quantized
+    /// colors before filtering along with the sorted, filtered (color, count) tuples.
+    fn build_histogram(&self) -> (usize, Vec<(QuantizedColor, u32)>) {
+        // begin by generating a histogram of quantized pixel values. in deterministic mode the
+        // histogram is kept ordered by the packed colors, otherwise it's in arbitrary order
+        let hist: Vec<(QuantizedColor, u64)> = if self.options.deterministic {
+            let mut hist = BTreeMap::new();
+            for (rgb, count) in self.weighted_rgb() {
+                let color = self.options.kind.quantize(rgb);
+                hist.entry(pack_color(color)).or_insert((color, 0)).1 += count;
+            }
+
+            hist.into_values().collect()
+        } else {
+            let mut hist = HashMap::new();
+            for (rgb, count) in self.weighted_rgb() {
+                let color = self.options.kind.quantize(rgb);
+                *hist.entry(color).or_insert(0) += count;
+            }
+
+            hist.into_iter().collect()
+        };
+
+        // convert the weights into counts, leaving out colors with no weight such as those of fully
+        // transparent pixels when weighting by alpha
+        let hist: Vec<_> = hist
+            .into_iter()
+            .map(|(color, weight)| (color, self.weight_to_count(weight)))
+            .filter(|&(_, count)| count > 0)
+            .collect();
+
+        // filter out unwanted colors from the histogram
+        let hist_len = hist.len();
+        let mut colors: Vec<_> = hist
+            .into_iter()
+            .filter(|&(color, _)| {
+                let rgb = self.options.kind.approximate_rgb(color);
+                self.should_allow_color(FilterStage::Input, rgb)
+            })
+            .collect();
+
+        // the colors have to be ordered at this point, so order them by their packed value unless
+        // they are ordered already
+        if !self.options.deterministic {
+            colors.sort_by_key(|&(color, _)| pack_color(color));
         }
+
+        (hist_len, colors)
     }
 
-    fn quantize_pixels(self, mut colors: Vec<(P, u32)>) -> Vec<Swatch> {
-        // create a priority queue of Vboxes with the first one containing all the given colors.
-        // Vbox comparison is based on their volume, reversed, so the queue always pops the
-        // largest Vbox by volume first
+    /// Multiply each histogram color's count by `1 + boost * saturation`, so saturated colors are
+    /// more likely to end up in their own Vbox. The counts are never boosted below 1.
+    fn boost_saturated_colors(&self, colors: &mut [(QuantizedColor, u32)]) {
+        for (color, count) in colors.iter_mut() {
+            let (_, saturation, _) = crate::rgb_to_hsl(self.options.kind.approximate_rgb(*color));
+            let boost = 1.0 + self.options.saturation_boost * saturation;
+            *count = (*count as f32 * boost).round().max(1.0) as u32;
+        }
+    }
+
+    fn quantize_pixels(
+        &self,
+        mut colors: Vec<(QuantizedColor, u32)>,
+        stats: &mut QuantizationStats,
+    ) -> Vec<Swatch> {
+        // split the Vboxes until there are enough colors or no more boxes to split
+        let boxes = self.split_boxes(&mut colors);
 
-        let mut pq = BinaryHeap::with_capacity(self.max_colors);
-        pq.push(Vbox::new(&mut colors));
+        // if there are fewer boxes than requested colors, the splitting ran out of boxes to split
+        stats.quantized_colors = boxes.len();
+        stats.terminated_early = boxes.len() < self.max_colors;
 
-        // go through the queue until there are enough colors or no more boxes to split
-        self.split_boxes(&mut pq);
+        // convert the remaining Vboxes into swatches
+        let mut swatches: Vec<_> = boxes
+            .iter()
+            .map(|vbox| match self.options.representative {
+                Representative::Mean if self.options.linear_averaging => {
+                    vbox.get_linear_average_color(self.options.kind)
+                }
+                Representative::Mean => vbox.get_average_color(self.options.kind),
+                Representative::Median => vbox.get_median_color(self.options.kind),
+            })
+            .collect();
+        drop(boxes);
+
+        for _ in 0..self.options.refine_iterations {
+            swatches = self.refine_swatches(&swatches, &colors);
+        }
+
+        swatches
+    }
+
+    /// Run a single k-means (Lloyd) iteration over the swatches: assign each histogram color to its
+    /// nearest swatch and return the population-weighted centroids of the colors assigned to each
+    /// swatch. Swatches with no colors assigned to them are dropped.
+    fn refine_swatches(
+        &self,
+        swatches: &[Swatch],
+        colors: &[(QuantizedColor, u32)],
+    ) -> Vec<Swatch> {
+        // population and weighted channel sums for each swatch
+        let mut sums = vec![(0, 0, 0, 0); swatches.len()];
+
+        for &(color, count) in colors {
+            let rgb = self.options.kind.approximate_rgb(color);
+            let Some(nearest) =
+                (0..swatches.len()).min_by_key(|&i| distance_squared(swatches[i].rgb(), rgb))
+            else {
+                break;
+            };
 
-        // return the remaining Vboxes converting them into swatches, filtering out unwanted colors
-        pq.iter()
-            .filter_map(|vbox| {
-                let swatch = vbox.get_average_color();
-                self.should_allow_color(swatch.rgb()).then_some(swatch)
+            let (pop, red_sum, green_sum, blue_sum) = &mut sums[nearest];
+            *pop += count as u64;
+            *red_sum += rgb.0 as u64 * count as u64;
+            *green_sum += rgb.1 as u64 * count as u64;
+            *blue_sum += rgb.2 as u64 * count as u64;
+        }
+
+        sums.into_iter()
+            .filter(|&(pop, ..)| pop > 0)
+            .map(|(pop, red_sum, green_sum, blue_sum)| {
+                let mean = |sum: u64| (sum as f32 / pop as f32).round() as u8;
+                let rgb = (mean(red_sum), mean(green_sum), mean(blue_sum));
+                Swatch::new(rgb, saturate_population(pop))
             })
             .collect()
     }
 
-    fn should_allow_color(&self, rgb: (u8, u8, u8)) -> bool {
+    fn should_allow_color(&self, stage: FilterStage, rgb: (u8, u8, u8)) -> bool {
         let hsl = crate::rgb_to_hsl(rgb);
         self.filters
             .iter()
-            .all(|filter| filter.is_allowed(rgb, hsl))
+            .all(|filter| filter.is_allowed(stage, rgb, hsl))
+    }
+
+    /// Split the colors into Vboxes until there are as many Vboxes as requested colors or no more
+    /// Vboxes to split, returning the Vboxes in the order they would be split in next. With the
+    /// `rayon` feature, images with many colors are split in parallel when enough colors are
+    /// requested.
+    fn split_boxes<'a>(&self, colors: &'a mut [(QuantizedColor, u32)]) -> Vec<Vbox<'a>> {
+        // create a priority queue of Vboxes with the first one containing all the given colors.
+        // Vbox comparison is based on their priority, which by default is their volume, so the
+        // queue always pops the largest Vbox by volume first
+        #[cfg(feature = "rayon")]
+        let parallel = colors.len() >= PARALLEL_SORT_THRESHOLD;
+        let queue = SplitQueue::new(Vbox::new(colors, self.options));
+
+        #[cfg(feature = "rayon")]
+        if parallel {
+            let subtrees = rayon::current_num_threads() * PARALLEL_SPLIT_BOXES_PER_THREAD;
+            if self.max_colors > subtrees {
+                return self.split_boxes_in_parallel(queue, subtrees);
+            }
+        }
+
+        self.split_boxes_serially(queue)
     }
 
-    fn split_boxes(&self, pq: &mut BinaryHeap<Vbox<'_, P>>) {
-        // keep splitting the largest box in the queue until there are as many Vboxes as requested
-        // colors
-        while pq.len() < self.max_colors {
-            // terminate early if the queue is empty
-            let Some(vbox) = pq.pop() else { return };
+    fn split_boxes_serially<'a>(&self, mut queue: SplitQueue<'a>) -> Vec<Vbox<'a>> {
+        // keep splitting the highest priority box in the queue until there are as many Vboxes as
+        // requested colors, terminating early if there are no more boxes to split
+        while queue.len() < self.max_colors && !queue.is_exhausted() {
+            queue.split_next();
+        }
+
+        queue.into_sorted_boxes()
+    }
+
+    /// Split the Vboxes in the queue like [`ColorCutQuantizer::split_boxes`] does, but once there
+    /// are as many Vboxes as the given amount of subtrees, split each of them in parallel.
+    ///
+    /// Each subtree owns a disjoint part of the colors, so the subtrees can be split independently
+    /// as long as it's known how many times each of them is split. Splitting serially always pops
+    /// the highest priority Vbox, which is the highest priority Vbox at the top of any subtree's
+    /// queue. So, the subtrees are split ahead of time in parallel, recording the key of each Vbox
+    /// they pop, and the records are merged to find how many of each subtree's steps splitting
+    /// serially would take. Each subtree is finally split again that many times, giving the same
+    /// Vboxes as splitting serially.
+    #[cfg(feature = "rayon")]
+    fn split_boxes_in_parallel<'a>(
+        &self,
+        mut queue: SplitQueue<'a>,
+        subtrees: usize,
+    ) -> Vec<Vbox<'a>> {
+        use rayon::iter::{
+            IndexedParallelIterator, IntoParallelIterator, IntoParallelRefMutIterator,
+            ParallelIterator,
+        };
+
+        // split serially until there are enough boxes to split in parallel
+        while queue.len() < self.max_colors.min(subtrees) && !queue.is_exhausted() {
+            queue.split_next();
+        }
+        if queue.len() >= self.max_colors || queue.is_exhausted() {
+            return queue.into_sorted_boxes();
+        }
+
+        let SplitQueue {
+            queue: roots,
+            mut unsplittable,
+            ..
+        } = queue;
+        let mut roots: Vec<_> = roots.into_iter().map(|vbox| vbox.colors).collect();
+        let mut count = roots.len() + unsplittable.len();
+
+        // split each subtree ahead of time, merging their steps until there are as many Vboxes as
+        // requested colors or no more boxes to split
+        let steps: Vec<_> = {
+            let mut trees: Vec<_> = roots
+                .iter_mut()
+                .map(|colors| Subtree {
+                    queue: SplitQueue::new(Vbox::new(colors, self.options)),
+                    steps: Vec::new(),
+                    consumed: 0,
+                })
+                .collect();
 
-            if vbox.can_split() {
-                // split the box in two and push them both back to the queue
-                let (left, right) = vbox.split_box();
+            'split: loop {
+                // split the subtrees that have run out of steps to merge by their share of the
+                // remaining splits
+                let batch = ((self.max_colors - count) / trees.len()).max(1);
+                trees
+                    .par_iter_mut()
+                    .filter(|tree| tree.consumed == tree.steps.len())
+                    .for_each(|tree| tree.split_ahead(batch));
+
+                while count < self.max_colors {
+                    // the next box to split is the one with the highest key across the subtrees.
+                    // if a subtree that still has boxes has run out of steps, it may have the next
+                    // box, so it has to be split further first
+                    let mut next: Option<(usize, VboxKey)> = None;
+                    for (index, tree) in trees.iter().enumerate() {
+                        match tree.steps.get(tree.consumed) {
+                            Some(step) if next.is_none_or(|(_, key)| step.key() > key) => {
+                                next = Some((index, step.key()))
+                            }
+                            Some(_) => (),
+                            None if tree.queue.is_exhausted() => (),
+                            None => continue 'split,
+                        }
+                    }
+
+                    // terminate early if every subtree has run out of boxes
+                    let Some((index, _)) = next else { break 'split };
+                    let tree = &mut trees[index];
+                    let step = tree.steps[tree.consumed];
+                    tree.consumed += 1;
+
+                    match step {
+                        SplitStep::Split(_) => count += 1,
+                        // the box stopped the splitting
+                        SplitStep::Unsplittable(_)
+                            if self.options.split_priority == SplitPriority::Volume =>
+                        {
+                            break 'split
+                        }
+                        SplitStep::Unsplittable(_) => (),
+                    }
+                }
+
+                break;
+            }
+
+            trees.iter().map(|tree| tree.consumed).collect()
+        };
+
+        // split each subtree again as many times as splitting serially would
+        let options = self.options;
+        let subtree_boxes: Vec<Vec<_>> = roots
+            .into_par_iter()
+            .zip(steps)
+            .map(|(colors, steps)| {
+                let mut queue = SplitQueue::new(Vbox::new(colors, options));
+                for _ in 0..steps {
+                    queue.split_next();
+                }
+
+                queue.into_boxes().collect()
+            })
+            .collect();
 
-                pq.push(left);
-                pq.push(right);
+        unsplittable.extend(subtree_boxes.into_iter().flatten());
+        unsplittable.sort_unstable_by(|left, right| right.cmp(left));
+        unsplittable
+    }
+}
 
-                continue;
+impl QuantizerKind {
+    /// Quantize an 8-bit sRGB color into this kind's color space.
+    fn quantize(self, (r, g, b): (u8, u8, u8)) -> QuantizedColor {
+        match self {
+            QuantizerKind::Rgb => (
+                modify_width(r, 8, QUANTIZE_WORD_WIDTH),
+                modify_width(g, 8, QUANTIZE_WORD_WIDTH),
+                modify_width(b, 8, QUANTIZE_WORD_WIDTH),
+            ),
+            QuantizerKind::OkLab => {
+                let (l, a, b) = color::rgb_to_oklab((r, g, b));
+                (
+                    quantize_component(l, OKLAB_L_RANGE),
+                    quantize_component(a, OKLAB_A_RANGE),
+                    quantize_component(b, OKLAB_B_RANGE),
+                )
             }
+        }
+    }
+
+    /// Convert a quantized color back into an approximate 8-bit sRGB color.
+    fn approximate_rgb(self, (c1, c2, c3): QuantizedColor) -> (u8, u8, u8) {
+        self.approximate_mean_rgb((c1 as f32, c2 as f32, c3 as f32))
+    }
 
-            // if the largest box cannot be split, there are no more boxes to split
-            return;
+    /// Convert the mean of quantized colors back into an approximate 8-bit sRGB color.
+    fn approximate_mean_rgb(self, (c1, c2, c3): (f32, f32, f32)) -> (u8, u8, u8) {
+        match self {
+            QuantizerKind::Rgb => (
+                modify_width(c1 as u8, QUANTIZE_WORD_WIDTH, 8),
+                modify_width(c2 as u8, QUANTIZE_WORD_WIDTH, 8),
+                modify_width(c3 as u8, QUANTIZE_WORD_WIDTH, 8),
+            ),
+            QuantizerKind::OkLab => color::oklab_to_rgb((
+                dequantize_component(c1, OKLAB_L_RANGE),
+                dequantize_component(c2, OKLAB_A_RANGE),
+                dequantize_component(c3, OKLAB_B_RANGE),
+            )),
         }
     }
 }
 
-impl<'a, P> Vbox<'a, P>
-where
-    P: image::Pixel<Subpixel = u8> + std::cmp::Eq + std::hash::Hash,
-{
-    fn new(colors: &'a mut [(P, u32)]) -> Self {
+impl<'a> Vbox<'a> {
+    fn new(colors: &'a mut [(QuantizedColor, u32)], options: QuantizerOptions) -> Self {
         // compute the boundaries of the Vbox to tightly fit around the colors within it
 
         let mut population = 0;
         let (mut min_red, mut max_red) = (QUANTIZE_WORD_MAX, 0);
         let (mut min_green, mut max_green) = (QUANTIZE_WORD_MAX, 0);
         let (mut min_blue, mut max_blue) = (QUANTIZE_WORD_MAX, 0);
+        let mut min_color = u32::MAX;
 
-        for (pixel, count) in colors.iter() {
-            let (r, g, b) = pixel_to_rgb(pixel);
-            population += count;
+        for &((r, g, b), count) in colors.iter() {
+            population += count as u64;
+            min_color = min_color.min(pack_color((r, g, b)));
 
-            if r < min_red {
-                min_red = r;
-            } else if r > max_red {
-                max_red = r;
-            }
-
-            if g < min_green {
-                min_green = g;
-            } else if g > max_green {
-                max_green = g;
-            }
-
-            if b < min_blue {
-                min_blue = b;
-            } else if b > max_blue {
-                max_blue = b;
-            }
+            min_red = min_red.min(r);
+            max_red = max_red.max(r);
+            min_green = min_green.min(g);
+            max_green = max_green.max(g);
+            min_blue = min_blue.min(b);
+            max_blue = max_blue.max(b);
         }
 
         Self {
             colors,
+            options,
             population,
             red_range: (min_red, max_red),
             green_range: (min_green, max_green),
             blue_range: (min_blue, max_blue),
+            min_color,
+        }
+    }
+
+    fn key(&self) -> VboxKey {
+        VboxKey {
+            priority: self.priority(),
+            min_color: self.min_color,
         }
     }
 
@@ -183,50 +678,91 @@ where
             * (self.blue_range.1 - self.blue_range.0 + 1) as u32
     }
 
-    /// Split the Vbox at the midpoint of its largest color dimension, returning two new Vboxes that
-    /// represent the boxes to the left and right of the split.
-    fn split_box(mut self) -> (Vbox<'a, P>, Vbox<'a, P>) {
-        assert!(self.can_split());
+    /// Returns the priority of the Vbox in the split queue. With uniform weighting, this is the
+    /// volume. With luma weighting, each side length of the box is raised to a power proportional
+    /// to its channel's weight, so boxes with a wide spread in the perceptually significant
+    /// channels are prioritized over boxes of the same volume. Depending on the split priority, the
+    /// volume may be multiplied by the population.
+    fn priority(&self) -> f32 {
+        let volume = match self.weights() {
+            None => self.volume() as f32,
+            Some((red_weight, green_weight, blue_weight)) => {
+                let length = |(min, max): (u8, u8)| (max - min + 1) as f32;
 
-        // sort the colors by the longest dimension so the midpoint can be searched for
-        self.sort_colors_by_longest_dimension();
+                length(self.red_range).powf(red_weight * 3.0)
+                    * length(self.green_range).powf(green_weight * 3.0)
+                    * length(self.blue_range).powf(blue_weight * 3.0)
+            }
+        };
 
-        let split_point = self.find_split_point();
-        let (left, right) = self.colors.split_at_mut(split_point);
+        match self.options.split_priority {
+            SplitPriority::Volume => volume,
+            SplitPriority::VolumeTimesPopulation => volume * self.population as f32,
+        }
+    }
 
-        (Vbox::new(left), Vbox::new(right))
+    /// Returns the channel weights, if the channels aren't weighted uniformly. The weighting only
+    /// applies to colors quantized in sRGB.
+    fn weights(&self) -> Option<(f32, f32, f32)> {
+        match (self.options.volume_weighting, self.options.kind) {
+            (VolumeWeighting::Luma, QuantizerKind::Rgb) => Some(LUMA_WEIGHTS),
+            _ => None,
+        }
     }
 
-    fn sort_colors_by_longest_dimension(&mut self) {
-        let longest_dimension = self.get_longest_dimension();
+    /// Split the Vbox at the midpoint of its largest color dimension, returning two new Vboxes that
+    /// represent the boxes to the left and right of the split.
+    fn split_box(mut self) -> (Vbox<'a>, Vbox<'a>) {
+        assert!(self.can_split());
 
-        self.colors.sort_by(|(lhs, _), (rhs, _)| {
-            let (lr, lg, lb) = pixel_to_rgb(lhs);
-            let (rr, rg, rb) = pixel_to_rgb(rhs);
+        // partition the colors around the split point along the longest dimension. neither side
+        // has to be sorted, since the new Vboxes compute their bounds from their colors anyway
+        let split_point = match self.options.split_method {
+            SplitMethod::PopulationMedian => self.select_split_point(),
+            SplitMethod::DimensionMidpoint => self.partition_at_midpoint(),
+        };
+        let (left, right) = self.colors.split_at_mut(split_point);
 
-            match longest_dimension {
-                Component::Red => lr.cmp(&rr),
-                Component::Green => lg.cmp(&rg),
-                Component::Blue => lb.cmp(&rb),
-            }
-        });
+        (
+            Vbox::new(left, self.options),
+            Vbox::new(right, self.options),
+        )
     }
 
-    /// Search for the index of the color after which their cumulative population sum has crossed
-    /// half the total population. This function assumes the colors have been sorted beforehand.
-    fn find_split_point(&self) -> usize {
+    /// Find the index to split the Vbox at around the color whose cumulative population sum, in the
+    /// order of the longest dimension, crosses half the total population. The colors are
+    /// partitioned so that every color before the index comes before every other color in that
+    /// order.
+    ///
+    /// Instead of sorting every color, the colors are repeatedly partitioned around the middle of
+    /// the range the crossing color is known to be in using quickselect, halving the range each
+    /// time. Colors with the same value in the longest dimension are ordered by their packed value
+    /// so the split doesn't depend on the order the colors happen to be in.
+    fn select_split_point(&mut self) -> usize {
+        let longest_dimension = self.get_longest_dimension();
         let midpoint = self.population / 2;
+
+        // the total population of the colors before the range
         let mut pop = 0;
+        let (mut low, mut high) = (0, self.colors.len());
 
-        // keep a total sum of all the color populations and return the first one that crosses the
-        // midpoint
-        for (i, (_, count)) in self.colors.iter().enumerate() {
-            pop += count;
+        while low < high {
+            let pivot = (high - low) / 2;
+            let (before, &mut (_, count), _) = self.colors[low..high]
+                .select_nth_unstable_by_key(pivot, |&(color, _)| {
+                    (longest_dimension.of(color), pack_color(color))
+                });
+            let before_pop: u64 = before.iter().map(|&(_, count)| count as u64).sum();
 
-            if pop >= midpoint {
-                // in case the first color (index 0) already crosses the midpoint, return the color
-                // after it in order to always split the Vbox in two
-                return i.max(1);
+            if pivot > 0 && pop + before_pop >= midpoint {
+                // the crossing color is before the pivot
+                high = low + pivot;
+            } else if pop + before_pop + count as u64 >= midpoint {
+                // the pivot is the crossing color
+                return self.balanced_split_point(low + pivot, pop + before_pop, count as u64);
+            } else {
+                pop += before_pop + count as u64;
+                low += pivot + 1;
             }
         }
 
@@ -235,14 +771,66 @@ where
         1
     }
 
+    /// Choose which side of the split the color crossing the population midpoint ends up on, given
+    /// its index, the population of the colors before it and its own count.
+    ///
+    /// When a single color holds a large part of the population, putting it on the wrong side can
+    /// leave one of the boxes with only a sliver of the population. Instead, the crossing color is
+    /// put on whichever side leaves the populations of the two boxes closest to each other, as long
+    /// as both boxes get at least one color. If both sides are equally balanced, the crossing
+    /// color is put on the right as before.
+    fn balanced_split_point(&self, index: usize, pop_before: u64, count: u64) -> usize {
+        let imbalance = |left_pop: u64| (2 * left_pop as i128 - self.population as i128).abs();
+
+        // splitting at the crossing color puts it in the right box, while splitting after it puts
+        // it in the left box. either way, the Vbox is always split in two
+        if index == 0 {
+            1
+        } else if index + 1 == self.colors.len() {
+            index
+        } else if imbalance(pop_before + count) < imbalance(pop_before) {
+            index + 1
+        } else {
+            index
+        }
+    }
+
+    /// Partition the colors around the midpoint of the Vbox's longest dimension, returning the
+    /// index of the first color past the midpoint.
+    fn partition_at_midpoint(&mut self) -> usize {
+        let longest_dimension = self.get_longest_dimension();
+        let (min, max) = match longest_dimension {
+            Component::Red => self.red_range,
+            Component::Green => self.green_range,
+            Component::Blue => self.blue_range,
+        };
+        let midpoint = (min as u32 + max as u32) / 2;
+
+        let mut split_point = 0;
+        for i in 0..self.colors.len() {
+            if longest_dimension.of(self.colors[i].0) as u32 <= midpoint {
+                self.colors.swap(i, split_point);
+                split_point += 1;
+            }
+        }
+
+        // always split the Vbox in two, even if every color is on the same side of the midpoint
+        if split_point == self.colors.len() {
+            1
+        } else {
+            split_point.max(1)
+        }
+    }
+
     fn can_split(&self) -> bool {
         self.colors.len() > 1
     }
 
     fn get_longest_dimension(&self) -> Component {
-        let red_length = self.red_range.1 - self.red_range.0;
-        let green_length = self.green_range.1 - self.green_range.0;
-        let blue_length = self.blue_range.1 - self.blue_range.0;
+        let (red_weight, green_weight, blue_weight) = self.weights().unwrap_or((1.0, 1.0, 1.0));
+        let red_length = (self.red_range.1 - self.red_range.0) as f32 * red_weight;
+        let green_length = (self.green_range.1 - self.green_range.0) as f32 * green_weight;
+        let blue_length = (self.blue_range.1 - self.blue_range.0) as f32 * blue_weight;
 
         if red_length >= green_length && red_length >= blue_length {
             Component::Red
@@ -253,70 +841,269 @@ where
         }
     }
 
-    fn get_average_color(&self) -> Swatch {
+    fn get_average_color(&self, kind: QuantizerKind) -> Swatch {
         // calculate the sum of all the color populations as well as weighted sums of each color
         // channel based on the color populations
         let (pop, red_sum, green_sum, blue_sum) = self.colors.iter().fold(
             (0, 0, 0, 0),
-            |(pop, red_sum, green_sum, blue_sum), (pixel, count)| {
-                let (r, g, b) = pixel_to_rgb(pixel);
+            |(pop, red_sum, green_sum, blue_sum), &((r, g, b), count)| {
+                let count = count as u64;
                 (
                     pop + count,
-                    red_sum + r as u32 * count,
-                    green_sum + g as u32 * count,
-                    blue_sum + b as u32 * count,
+                    red_sum + r as u64 * count,
+                    green_sum + g as u64 * count,
+                    blue_sum + b as u64 * count,
                 )
             },
         );
 
         // calculate the means of the channel weighted sums...
-        let red_mean = red_sum as f32 / pop as f32;
-        let green_mean = green_sum as f32 / pop as f32;
-        let blue_mean = blue_sum as f32 / pop as f32;
+        let red_mean = (red_sum as f64 / pop as f64) as f32;
+        let green_mean = (green_sum as f64 / pop as f64) as f32;
+        let blue_mean = (blue_sum as f64 / pop as f64) as f32;
+
+        // ...and convert them back into 8-bit sRGB
+        let rgb = kind.approximate_mean_rgb((red_mean, green_mean, blue_mean));
+
+        Swatch::new(rgb, saturate_population(pop))
+    }
+
+    /// Like [`Vbox::get_average_color`], but the colors are averaged in linear light instead of
+    /// directly in sRGB. Averaging sRGB values biases the result towards darker colors since sRGB
+    /// is non-linear, so this gives a perceptually truer representative color.
+    fn get_linear_average_color(&self, kind: QuantizerKind) -> Swatch {
+        // calculate the sum of all the color populations as well as the populations of each value
+        // of each color channel. summing the values in linear light only after that keeps the
+        // result the same regardless of the order the colors are in
+        let mut pop = 0;
+        let mut channel_counts = [[0u64; 256]; 3];
+        for &(color, count) in self.colors.iter() {
+            let (r, g, b) = kind.approximate_rgb(color);
+            pop += count as u64;
+            channel_counts[0][r as usize] += count as u64;
+            channel_counts[1][g as usize] += count as u64;
+            channel_counts[2][b as usize] += count as u64;
+        }
+
+        // calculate the means of the channels in linear light and encode them back into sRGB
+        let mean = |counts: &[u64; 256]| {
+            let sum: f64 = (0..=u8::MAX)
+                .zip(counts)
+                .filter(|&(_, &count)| count > 0)
+                .map(|(value, &count)| color::srgb_to_linear(value) as f64 * count as f64)
+                .sum();
+            color::linear_to_srgb((sum / pop as f64) as f32)
+        };
+        let [red, green, blue] = channel_counts.each_ref().map(mean);
+
+        Swatch::new((red, green, blue), saturate_population(pop))
+    }
+
+    /// Returns the color at the population median of the Vbox along its longest dimension, with the
+    /// Vbox's population.
+    fn get_median_color(&self, kind: QuantizerKind) -> Swatch {
+        let longest_dimension = self.get_longest_dimension();
+        let mut colors = self.colors.to_vec();
+        sort_colors_by_key(&mut colors, |&(color, _)| {
+            (longest_dimension.of(color), pack_color(color))
+        });
+
+        // find the first color after which the cumulative population has reached half the total
+        let midpoint = self.population.div_ceil(2);
+        let mut pop = 0;
+        let median = colors
+            .iter()
+            .find(|&&(_, count)| {
+                pop += count as u64;
+                pop >= midpoint
+            })
+            .or(colors.last())
+            .map_or((0, 0, 0), |&(color, _)| color);
 
-        // ...and quantize them back into 8 bits
-        let red_quantized = modify_width(red_mean as u8, QUANTIZE_WORD_WIDTH, 8);
-        let green_quantized = modify_width(green_mean as u8, QUANTIZE_WORD_WIDTH, 8);
-        let blue_quantized = modify_width(blue_mean as u8, QUANTIZE_WORD_WIDTH, 8);
+        Swatch::new(kind.approximate_rgb(median), saturate_population(self.population))
+    }
+}
 
-        Swatch::new((red_quantized, green_quantized, blue_quantized), pop)
+impl Component {
+    /// Returns the value of this component of a color.
+    fn of(&self, (r, g, b): QuantizedColor) -> u8 {
+        match self {
+            Component::Red => r,
+            Component::Green => g,
+            Component::Blue => b,
+        }
     }
 }
 
-impl<P> Eq for Vbox<'_, P> where P: image::Pixel<Subpixel = u8> + std::cmp::Eq + std::hash::Hash {}
-impl<P> PartialEq for Vbox<'_, P>
-where
-    P: image::Pixel<Subpixel = u8> + std::cmp::Eq + std::hash::Hash,
-{
+impl Eq for Vbox<'_> {}
+impl PartialEq for Vbox<'_> {
     fn eq(&self, other: &Self) -> bool {
-        self.volume() == other.volume()
+        self.cmp(other).is_eq()
     }
 }
 
-impl<P> Ord for Vbox<'_, P>
-where
-    P: image::Pixel<Subpixel = u8> + std::cmp::Eq + std::hash::Hash,
-{
+impl Ord for Vbox<'_> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.volume().cmp(&self.volume())
+        self.key().cmp(&other.key())
     }
 }
 
-impl<P> PartialOrd for Vbox<'_, P>
-where
-    P: image::Pixel<Subpixel = u8> + std::cmp::Eq + std::hash::Hash,
-{
+impl Eq for VboxKey {}
+impl Ord for VboxKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // of the Vboxes with the same priority, the one with the smallest color is split first
+        self.priority
+            .total_cmp(&other.priority)
+            .then_with(|| other.min_color.cmp(&self.min_color))
+    }
+}
+
+impl PartialOrd for VboxKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> SplitQueue<'a> {
+    fn new(vbox: Vbox<'a>) -> Self {
+        Self {
+            queue: BinaryHeap::from([vbox]),
+            unsplittable: Vec::new(),
+            stopped: false,
+        }
+    }
+
+    /// Returns the amount of Vboxes in the queue, including the Vboxes that cannot be split.
+    fn len(&self) -> usize {
+        self.queue.len() + self.unsplittable.len()
+    }
+
+    /// Returns whether there are no more Vboxes to split.
+    fn is_exhausted(&self) -> bool {
+        self.stopped || self.queue.is_empty()
+    }
+
+    /// Pop the highest priority Vbox off the queue and split it, pushing both halves back to the
+    /// queue. Returns the step taken, or `None` if there are no more Vboxes to split.
+    fn split_next(&mut self) -> Option<SplitStep> {
+        if self.stopped {
+            return None;
+        }
+
+        let vbox = self.queue.pop()?;
+        let key = vbox.key();
+
+        if vbox.can_split() {
+            // split the box in two and push them both back to the queue
+            let (left, right) = vbox.split_box();
+
+            self.queue.push(left);
+            self.queue.push(right);
+
+            return Some(SplitStep::Split(key));
+        }
+
+        // if the largest box by volume cannot be split, there are no more boxes to split. put it
+        // back so its colors aren't lost. when the priority accounts for population, a smaller box
+        // may still be splittable, so set the box aside and keep going
+        if vbox.options.split_priority == SplitPriority::Volume {
+            self.queue.push(vbox);
+            self.stopped = true;
+        } else {
+            self.unsplittable.push(vbox);
+        }
+
+        Some(SplitStep::Unsplittable(key))
+    }
+
+    /// Returns every Vbox in the queue, including the Vboxes that cannot be split.
+    fn into_boxes(self) -> impl Iterator<Item = Vbox<'a>> {
+        self.queue.into_iter().chain(self.unsplittable)
+    }
+
+    /// Returns every Vbox in the queue, including the Vboxes that cannot be split, in the order
+    /// they would be split in next.
+    fn into_sorted_boxes(self) -> Vec<Vbox<'a>> {
+        let mut boxes: Vec<_> = self.into_boxes().collect();
+        boxes.sort_unstable_by(|left, right| right.cmp(left));
+        boxes
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl SplitStep {
+    fn key(self) -> VboxKey {
+        match self {
+            SplitStep::Split(key) | SplitStep::Unsplittable(key) => key,
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Subtree<'_> {
+    /// Take up to the given amount of further steps splitting the subtree.
+    fn split_ahead(&mut self, steps: usize) {
+        for _ in 0..steps {
+            match self.queue.split_next() {
+                Some(step) => self.steps.push(step),
+                None => break,
+            }
+        }
+    }
+}
+
+impl PartialOrd for Vbox<'_> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-fn pixel_to_rgb<P>(pixel: &P) -> (u8, u8, u8)
+/// Sort colors by the given key. With the `rayon` feature, large amounts of colors are sorted in
+/// parallel.
+fn sort_colors_by_key<K, F>(colors: &mut [(QuantizedColor, u32)], key: F)
 where
-    P: image::Pixel<Subpixel = u8>,
+    K: Ord + Send,
+    F: Fn(&(QuantizedColor, u32)) -> K + Sync,
 {
-    let rgb = pixel.to_rgb();
-    (rgb.0[0], rgb.0[1], rgb.0[2])
+    #[cfg(feature = "rayon")]
+    if colors.len() >= PARALLEL_SORT_THRESHOLD {
+        use rayon::slice::ParallelSliceMut;
+
+        colors.par_sort_unstable_by_key(key);
+        return;
+    }
+
+    colors.sort_unstable_by_key(key);
+}
+
+/// Combine a color's components into a single integer where each component is the quantization word
+/// width long.
+fn pack_color((r, g, b): QuantizedColor) -> u32 {
+    ((r as u32) << (QUANTIZE_WORD_WIDTH * 2)) | ((g as u32) << QUANTIZE_WORD_WIDTH) | b as u32
+}
+
+/// Convert a population summed over many colors into a swatch population, saturating at the largest
+/// population a swatch can have.
+fn saturate_population(population: u64) -> u32 {
+    population.min(u32::MAX as u64) as u32
+}
+
+fn distance_squared((lr, lg, lb): (u8, u8, u8), (rr, rg, rb): (u8, u8, u8)) -> u32 {
+    let dr = lr.abs_diff(rr) as u32;
+    let dg = lg.abs_diff(rg) as u32;
+    let db = lb.abs_diff(rb) as u32;
+    dr * dr + dg * dg + db * db
+}
+
+/// Quantize a component in a given range into the quantization word width.
+fn quantize_component(value: f32, (min, max): (f32, f32)) -> u8 {
+    let normalized = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    (normalized * QUANTIZE_WORD_MAX as f32).round() as u8
+}
+
+/// Convert a quantized component back into its original range.
+fn dequantize_component(value: f32, (min, max): (f32, f32)) -> f32 {
+    min + value / QUANTIZE_WORD_MAX as f32 * (max - min)
 }
 
 fn modify_width(value: u8, current_width: u32, target_width: u32) -> u8 {
@@ -326,3 +1113,184 @@ fn modify_width(value: u8, current_width: u32, target_width: u32) -> u8 {
         value.wrapping_shr(current_width - target_width)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_colors(rng: &mut StdRng, len: usize) -> Vec<(QuantizedColor, u32)> {
+        let mut colors = BTreeMap::new();
+        while colors.len() < len {
+            let color = (
+                rng.gen_range(0..=QUANTIZE_WORD_MAX),
+                rng.gen_range(0..=QUANTIZE_WORD_MAX),
+                rng.gen_range(0..=QUANTIZE_WORD_MAX),
+            );
+            colors.insert(pack_color(color), (color, rng.gen_range(1..1000)));
+        }
+
+        colors.into_values().collect()
+    }
+
+    /// Find the split point by sorting every color instead of using quickselect.
+    fn sorted_split_point(vbox: &mut Vbox) -> usize {
+        let longest_dimension = vbox.get_longest_dimension();
+        vbox.colors
+            .sort_unstable_by_key(|&(color, _)| (longest_dimension.of(color), pack_color(color)));
+
+        let midpoint = vbox.population / 2;
+        let mut pop = 0;
+        for (index, &(_, count)) in vbox.colors.iter().enumerate() {
+            if pop + count as u64 >= midpoint {
+                return vbox.balanced_split_point(index, pop, count as u64);
+            }
+
+            pop += count as u64;
+        }
+
+        1
+    }
+
+    #[test]
+    fn selected_split_matches_sorted_split() {
+        let mut rng = StdRng::seed_from_u64(911);
+
+        for len in [2, 3, 10, 100, 1000] {
+            for _ in 0..20 {
+                let mut colors = random_colors(&mut rng, len);
+                let mut sorted = colors.clone();
+
+                let mut vbox = Vbox::new(&mut colors, QuantizerOptions::default());
+                let split_point = vbox.select_split_point();
+                let longest_dimension = vbox.get_longest_dimension();
+                let key = |&(color, _): &(QuantizedColor, u32)| {
+                    (longest_dimension.of(color), pack_color(color))
+                };
+
+                let mut sorted_vbox = Vbox::new(&mut sorted, QuantizerOptions::default());
+                assert_eq!(split_point, sorted_split_point(&mut sorted_vbox));
+
+                // both sides hold the same colors as when sorting, in any order
+                let mut left = colors[..split_point].to_vec();
+                left.sort_unstable_by_key(key);
+                assert_eq!(left, sorted[..split_point]);
+
+                let max_left = colors[..split_point].iter().map(key).max();
+                let min_right = colors[split_point..].iter().map(key).min();
+                assert!(max_left < min_right);
+            }
+        }
+    }
+
+    #[test]
+    fn skewed_split_keeps_both_boxes_balanced() {
+        // colors along the red axis, with one color holding nearly all of the population at
+        // various positions
+        for dominant in [0, 1, 5, 8, 9] {
+            let mut colors: Vec<_> = (0..10)
+                .map(|r| ((r * 3, 0, 0), if r == dominant { 10_000 } else { 10 }))
+                .collect();
+            let counts: Vec<u64> = colors.iter().map(|&(_, count)| count as u64).collect();
+            let total: u64 = counts.iter().sum();
+
+            // the smallest population imbalance any split of the sorted colors could give
+            let best_imbalance = (1..counts.len())
+                .map(|index| {
+                    let left: u64 = counts[..index].iter().sum();
+                    left.abs_diff(total - left)
+                })
+                .min()
+                .unwrap();
+
+            let (left, right) = Vbox::new(&mut colors, QuantizerOptions::default()).split_box();
+            assert!(!left.colors.is_empty() && !right.colors.is_empty());
+            assert_eq!(left.population + right.population, total);
+            assert_eq!(left.population.abs_diff(right.population), best_imbalance);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_split_matches_serial_split() {
+        let mut rng = StdRng::seed_from_u64(910);
+        let colors = random_colors(&mut rng, 3000);
+
+        let option_sets = [
+            QuantizerOptions::default(),
+            QuantizerOptions {
+                split_priority: SplitPriority::VolumeTimesPopulation,
+                ..Default::default()
+            },
+            QuantizerOptions {
+                split_method: SplitMethod::DimensionMidpoint,
+                volume_weighting: VolumeWeighting::Luma,
+                ..Default::default()
+            },
+        ];
+
+        for options in option_sets {
+            for max_colors in [2, 16, 100, 1000, 4000] {
+                let quantizer = ColorCutQuantizer::<image::Rgb<u8>>::new(
+                    Vec::new(),
+                    max_colors,
+                    Vec::new(),
+                    options,
+                );
+                let boxes = |boxes: Vec<Vbox>| -> Vec<_> {
+                    boxes
+                        .into_iter()
+                        .map(|vbox| {
+                            let mut colors = vbox.colors.to_vec();
+                            colors.sort_unstable_by_key(|&(color, _)| pack_color(color));
+                            (vbox.key(), colors, vbox.get_linear_average_color(options.kind))
+                        })
+                        .collect()
+                };
+
+                let mut serial_colors = colors.clone();
+                let queue = SplitQueue::new(Vbox::new(&mut serial_colors, options));
+                let serial = boxes(quantizer.split_boxes_serially(queue));
+                assert_eq!(serial.len(), max_colors.min(colors.len()));
+
+                for subtrees in [1, 2, 3, 8, 64] {
+                    let mut parallel_colors = colors.clone();
+                    let queue = SplitQueue::new(Vbox::new(&mut parallel_colors, options));
+                    let parallel = boxes(quantizer.split_boxes_in_parallel(queue, subtrees));
+
+                    assert_eq!(serial, parallel, "{max_colors} colors, {subtrees} subtrees");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn linear_average_of_black_and_white_is_lighter() {
+        let white = (QUANTIZE_WORD_MAX, QUANTIZE_WORD_MAX, QUANTIZE_WORD_MAX);
+        let mut colors = vec![((0, 0, 0), 1), (white, 1)];
+        let vbox = Vbox::new(&mut colors, QuantizerOptions::default());
+
+        let (naive, _, _) = vbox.get_average_color(QuantizerKind::Rgb).rgb();
+        let (linear, _, _) = vbox.get_linear_average_color(QuantizerKind::Rgb).rgb();
+
+        // white quantizes into 248, so the naive average is 120
+        assert_eq!(naive, 120);
+        assert!(linear > 176);
+    }
+
+    #[test]
+    fn mean_and_median_differ_on_a_bimodal_box() {
+        let white = (QUANTIZE_WORD_MAX, QUANTIZE_WORD_MAX, QUANTIZE_WORD_MAX);
+        let mut colors = vec![((0, 0, 0), 10), (white, 11)];
+        let vbox = Vbox::new(&mut colors, QuantizerOptions::default());
+
+        // the mean is a gray between the two colors, while the median is the more common color
+        let mean = vbox.get_average_color(QuantizerKind::Rgb);
+        let median = vbox.get_median_color(QuantizerKind::Rgb);
+
+        assert_eq!(mean.rgb(), (128, 128, 128));
+        assert_eq!(median.rgb(), (248, 248, 248));
+        assert_eq!(mean.population(), median.population());
+    }
+}
@@ -0,0 +1,51 @@
+use image::{ImageBuffer, Rgb, RgbImage};
+
+use crate::pixel::QuantizablePixel;
+
+/// Map an image onto a duotone gradient between two colors.
+///
+/// Each pixel's relative luminance (see [`crate::Swatch::relative_luminance`]) picks a point on the
+/// gradient from the shadow color at a luminance of 0 to the highlight color at a luminance of 1.
+/// The colors are interpolated in sRGB. Palette colors make for natural duotone pairs, such as
+/// [`crate::Palette::dark_muted_color`] and [`crate::Palette::light_vibrant_color`].
+pub fn duotone<P>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    shadow: (u8, u8, u8),
+    highlight: (u8, u8, u8),
+) -> RgbImage
+where
+    P: image::Pixel<Subpixel = u8>,
+{
+    let lerp = |from: u8, to: u8, t: f32| {
+        (from as f32 + (to as f32 - from as f32) * t).round() as u8
+    };
+
+    ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+        let t = crate::color::relative_luminance(image.get_pixel(x, y).rgb());
+
+        Rgb([
+            lerp(shadow.0, highlight.0, t),
+            lerp(shadow.1, highlight.1, t),
+            lerp(shadow.2, highlight.2, t),
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+
+    #[test]
+    fn mid_gray_maps_to_the_midpoint() {
+        // the sRGB gray with a relative luminance of roughly 0.5
+        let image = GrayImage::from_pixel(4, 4, Luma([188]));
+        let mapped = duotone(&image, (20, 40, 200), (220, 140, 0));
+
+        for &Rgb([r, g, b]) in mapped.pixels() {
+            assert!(r.abs_diff(120) <= 1 && g.abs_diff(90) <= 1 && b.abs_diff(100) <= 1);
+        }
+        assert_eq!(mapped.dimensions(), (4, 4));
+    }
+}
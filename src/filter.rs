@@ -1,6 +1,13 @@
 const BLACK_MAX_LIGHTNESS: f32 = 0.05;
 const WHITE_MIN_LIGHTNESS: f32 = 0.95;
 
+// the hue range and maximum saturation of the colors near the red side of the I line
+const RED_I_LINE_HUE_RANGE: (f32, f32) = (10.0, 37.0);
+const RED_I_LINE_MAX_SATURATION: f32 = 0.82;
+
+const GRAYSCALE_BLACK_MAX_LIGHTNESS: f32 = 0.01;
+const GRAYSCALE_WHITE_MIN_LIGHTNESS: f32 = 0.99;
+
 /// A trait used to implement filters for the image quantization process.
 ///
 /// During the image quantization process, filters are used to remove colors from the quantization
@@ -8,32 +15,197 @@ const WHITE_MIN_LIGHTNESS: f32 = 0.95;
 /// filtered. This trait allows the library consumer to implement custom filters.
 ///
 /// See [`crate::PaletteBuilder::add_filter`] on how to add filters to the quantization process.
+/// Filters added to a builder have to be [`Send`], so the builder can be moved onto another thread
+/// to generate the palette there.
 pub trait Filter {
     /// Return whether a given color should be allowed or not. The same color is given in both sRGB
     /// and HSL for convenience.
     fn is_allowed(&self, rgb: (u8, u8, u8), hsl: (f32, f32, f32)) -> bool;
 }
 
+/// A boxed [`Filter`], as added to a [`crate::PaletteBuilder`] with
+/// [`crate::PaletteBuilder::add_filters`]. The filter has to be [`Send`] so a builder holding it can
+/// be moved onto another thread.
+pub type BoxedFilter = Box<dyn Filter + Send>;
+
+/// A stage of the quantization process filters are evaluated in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FilterStage {
+    /// The colors in the image's histogram, before quantizing.
+    Input,
+    /// The average colors of the quantized swatches.
+    Output,
+}
+
+/// A filter along with the stages of the quantization process it's evaluated in.
+pub(crate) struct StagedFilter {
+    filter: BoxedFilter,
+    input: bool,
+    output: bool,
+}
+
+impl StagedFilter {
+    pub(crate) fn new(filter: BoxedFilter, input: bool, output: bool) -> Self {
+        Self {
+            filter,
+            input,
+            output,
+        }
+    }
+
+    /// Return whether a given color should be allowed in a given stage. Colors are always allowed
+    /// in stages the filter isn't evaluated in.
+    pub(crate) fn is_allowed(
+        &self,
+        stage: FilterStage,
+        rgb: (u8, u8, u8),
+        hsl: (f32, f32, f32),
+    ) -> bool {
+        !self.is_evaluated(stage) || self.filter.is_allowed(rgb, hsl)
+    }
+
+    /// Return whether the filter is evaluated in a given stage.
+    pub(crate) fn is_evaluated(&self, stage: FilterStage) -> bool {
+        match stage {
+            FilterStage::Input => self.input,
+            FilterStage::Output => self.output,
+        }
+    }
+}
+
 /// The default filter included in every [`crate::PaletteBuilder`] by default.
 ///
 /// This filter will disallow colors very close to black, colors very close to white, and colors
-/// near the red I line, whatever that is.
-#[derive(Debug)]
-pub struct DefaultFilter;
+/// near the red side of the I line. By default, colors with a lightness of at most 0.05 are
+/// considered black and colors with a lightness of at least 0.95 are considered white. The
+/// thresholds can be changed with [`DefaultFilter::with_thresholds`].
+///
+/// The I line is the in-phase axis of the YIQ color space, whose orange-red end is where human skin
+/// tones lie. Colors with a hue between 10 and 37 degrees and a saturation of at most 0.82 are
+/// disallowed to keep skin tones from taking over palettes of photos with people in them. In
+/// images where these colors are wanted, the rule can be adjusted with
+/// [`DefaultFilter::with_red_i_line`] or disabled with [`DefaultFilter::without_red_i_line`].
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultFilter {
+    black_max_lightness: f32,
+    white_min_lightness: f32,
+    // min hue, max hue, max saturation
+    red_i_line: Option<(f32, f32, f32)>,
+}
+
+impl DefaultFilter {
+    /// Returns a new default filter with custom black and white thresholds. Colors with a
+    /// lightness of at most `black_max` are disallowed as black, and colors with a lightness of at
+    /// least `white_min` are disallowed as white.
+    pub fn with_thresholds(black_max: f32, white_min: f32) -> DefaultFilter {
+        let (min_hue, max_hue) = RED_I_LINE_HUE_RANGE;
+
+        DefaultFilter {
+            black_max_lightness: black_max,
+            white_min_lightness: white_min,
+            red_i_line: Some((min_hue, max_hue, RED_I_LINE_MAX_SATURATION)),
+        }
+    }
+
+    /// Returns a copy of this filter that disallows colors with a hue within the given range of
+    /// degrees, inclusive, and a saturation of at most `max_saturation` as being near the red side
+    /// of the I line.
+    pub fn with_red_i_line(self, (min_hue, max_hue): (f32, f32), max_saturation: f32) -> Self {
+        Self {
+            red_i_line: Some((min_hue, max_hue, max_saturation)),
+            ..self
+        }
+    }
+
+    /// Returns a copy of this filter that allows colors near the red side of the I line, only
+    /// disallowing colors close to black or white.
+    pub fn without_red_i_line(self) -> Self {
+        Self {
+            red_i_line: None,
+            ..self
+        }
+    }
+
+    fn is_near_red_i_line(&self, h: f32, s: f32) -> bool {
+        self.red_i_line.is_some_and(|(min_hue, max_hue, max_saturation)| {
+            (min_hue..=max_hue).contains(&h) && s <= max_saturation
+        })
+    }
+}
+
+impl Default for DefaultFilter {
+    fn default() -> Self {
+        Self::with_thresholds(BLACK_MAX_LIGHTNESS, WHITE_MIN_LIGHTNESS)
+    }
+}
+
 impl Filter for DefaultFilter {
     fn is_allowed(&self, _: (u8, u8, u8), (h, s, l): (f32, f32, f32)) -> bool {
-        !is_black(l) && !is_white(l) && !is_near_red_i_line(h, s)
+        let is_black = l <= self.black_max_lightness;
+        let is_white = l >= self.white_min_lightness;
+
+        !is_black && !is_white && !self.is_near_red_i_line(h, s)
     }
 }
 
-fn is_black(l: f32) -> bool {
-    l <= BLACK_MAX_LIGHTNESS
+/// The filter used in place of the default filter in grayscale mode.
+///
+/// The default filter's black and white thresholds reject too many grays for grayscale images, so
+/// this filter only disallows colors extremely close to black or white. It's only evaluated on the
+/// quantized swatches, so the darkest and lightest pixels still count towards the swatches next to
+/// them.
+#[derive(Debug)]
+pub(crate) struct GrayscaleFilter;
+impl Filter for GrayscaleFilter {
+    fn is_allowed(&self, _: (u8, u8, u8), (_, _, l): (f32, f32, f32)) -> bool {
+        l > GRAYSCALE_BLACK_MAX_LIGHTNESS && l < GRAYSCALE_WHITE_MIN_LIGHTNESS
+    }
 }
 
-fn is_white(l: f32) -> bool {
-    l >= WHITE_MIN_LIGHTNESS
+/// A filter disallowing colors with an HSL saturation below a minimum, such as grays and colors
+/// close to gray. See [`crate::PaletteBuilder::minimum_saturation`].
+#[derive(Debug)]
+pub(crate) struct MinimumSaturationFilter {
+    pub(crate) minimum_saturation: f32,
+}
+impl Filter for MinimumSaturationFilter {
+    fn is_allowed(&self, _: (u8, u8, u8), (_, s, _): (f32, f32, f32)) -> bool {
+        s >= self.minimum_saturation
+    }
 }
 
-fn is_near_red_i_line(h: f32, s: f32) -> bool {
-    (10.0..=37.0).contains(&h) && s <= 0.82
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_allowed(filter: impl Filter, rgb: (u8, u8, u8)) -> bool {
+        filter.is_allowed(rgb, crate::rgb_to_hsl(rgb))
+    }
+
+    #[test]
+    fn black_threshold_is_configurable() {
+        // a dark gray with a lightness of roughly 0.08
+        let dark_gray = (20, 20, 20);
+
+        assert!(!is_allowed(DefaultFilter::default(), (10, 10, 10)));
+        assert!(is_allowed(DefaultFilter::with_thresholds(0.02, 0.95), dark_gray));
+        assert!(!is_allowed(DefaultFilter::with_thresholds(0.10, 0.95), dark_gray));
+        assert!(!is_allowed(DefaultFilter::with_thresholds(0.02, 0.95), (250, 250, 250)));
+    }
+
+    #[test]
+    fn red_i_line_is_adjustable() {
+        // a hue of roughly 9 degrees, just below the default band, and roughly 30 degrees within it
+        let orange_red = (191, 83, 64);
+        let orange = (191, 127, 64);
+
+        assert!(is_allowed(DefaultFilter::default(), orange_red));
+        assert!(!is_allowed(DefaultFilter::default(), orange));
+        let widened = DefaultFilter::default().with_red_i_line((0.0, 37.0), 0.82);
+        assert!(!is_allowed(widened, orange_red));
+        assert!(!is_allowed(widened, orange));
+        let disabled = DefaultFilter::default().without_red_i_line();
+        assert!(is_allowed(disabled, orange_red));
+        assert!(is_allowed(disabled, orange));
+    }
 }
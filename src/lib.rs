@@ -22,8 +22,17 @@
 //!
 //! [Android Jetpack license.](https://github.com/androidx/androidx/blob/7b7922489f9a7572f4462558691bf5550dd65c26/LICENSE.txt)
 
+#[cfg(feature = "cache")]
+mod cache;
+mod color;
 mod color_cut_quantizer;
+mod css;
+mod duotone;
 mod filter;
+mod lightness_quantizer;
+mod pixel;
+mod prepared;
+mod scheme;
 mod swatch;
 mod target;
 
@@ -32,25 +41,63 @@ pub const DEFAULT_CALCULATE_NUMBER_COLORS: usize = 16;
 /// The default area to resize the given image to before quantizing;
 pub const DEFAULT_RESIZE_IMAGE_AREA: u32 = 112 * 112;
 
-use std::collections::{HashMap, HashSet};
+/// The fraction of the image's edge pixels that must share a color for it to be detected as the
+/// background color.
+const BACKGROUND_MIN_EDGE_FRACTION: f32 = 0.6;
+/// The maximum difference in any color channel for a pixel to be considered part of the background.
+const BACKGROUND_COLOR_TOLERANCE: u8 = 16;
+/// The HSL saturation at or below which a color is considered too achromatic to have a meaningful
+/// hue in a hue histogram.
+const HUE_HISTOGRAM_MIN_SATURATION: f32 = 0.1;
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub use image;
-use image::{math::Rect, GenericImageView, ImageBuffer};
+use image::{math::Rect, GenericImageView, ImageBuffer, Rgb};
+#[cfg(feature = "palette")]
+pub use palette;
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
 
-use crate::color_cut_quantizer::ColorCutQuantizer;
+use crate::{
+    color_cut_quantizer::{ColorCutQuantizer, QuantizerOptions},
+    filter::{GrayscaleFilter, MinimumSaturationFilter, StagedFilter},
+    lightness_quantizer::LightnessQuantizer,
+    pixel::QuantizablePixel,
+};
+#[cfg(feature = "cache")]
+pub use crate::cache::PaletteCache;
 pub use crate::{
-    filter::{DefaultFilter, Filter},
+    color::{parse_hex_palette, ColorBlindness, ParseError, ToneMapping},
+    color_cut_quantizer::{
+        QuantizerKind, Representative, SplitMethod, SplitPriority, VolumeWeighting,
+    },
+    duotone::duotone,
+    filter::{BoxedFilter, DefaultFilter, Filter},
+    prepared::PreparedImage,
+    scheme::harmonious_scheme,
     swatch::Swatch,
-    target::Target,
+    target::{PresetTarget, Target, TargetSpace},
 };
 
 /// A color palette derived from an image.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Palette {
     swatches: Vec<Swatch>,
     targets: Vec<Target>,
-    selected_swatches: HashMap<u64, Option<Swatch>>,
+    // ordered by target ID so the palette's contents are always in the same order
+    selected_swatches: BTreeMap<u64, Option<Swatch>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    fallback_targets: BTreeSet<u64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    rejected_swatches: Vec<Swatch>,
+    // the scoring the swatches were selected with, for explaining the selection
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scoring: ScoringOptions,
 }
 
 /// A builder for a new [Palette].
@@ -63,7 +110,130 @@ where
     maximum_color_count: usize,
     resize_area: Option<u32>,
     region: Option<Rect>,
-    filters: Vec<Box<dyn Filter>>,
+    // the custom filters, evaluated after the default filter if it's used
+    filters: Vec<StagedFilter>,
+    use_default_filter: bool,
+    minimum_saturation: Option<f32>,
+    grayscale: bool,
+    quantizer_options: QuantizerOptions,
+    minimum_population_fraction: f32,
+    minimum_swatch_population: u32,
+    keep_rejected_swatches: bool,
+    exclude_background: bool,
+    premultiplied: bool,
+    // hue and lightness tolerances
+    merge_hsl: Option<(f32, f32)>,
+    sample_stride: usize,
+    sample_count: Option<usize>,
+    sample_seed: Option<u64>,
+    weighted_colors: Vec<((u8, u8, u8), u32)>,
+    scoring: ScoringOptions,
+}
+
+/// A function used to score a swatch for a target, given the dominant swatch in the palette.
+type ScoreFn = Arc<dyn Fn(Swatch, Option<Swatch>, Target) -> f32 + Send + Sync>;
+
+/// Options controlling how swatches are scored and selected for targets.
+#[derive(Clone, Default)]
+struct ScoringOptions {
+    score_fn: Option<ScoreFn>,
+    population_scale: PopulationScale,
+    population_reference: PopulationReference,
+    fallback: TargetFallback,
+    relax_steps: u32,
+    relax_step_size: f32,
+}
+
+impl std::fmt::Debug for ScoringOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the score function can't be formatted, so only its presence is shown
+        f.debug_struct("ScoringOptions")
+            .field("score_fn", &self.score_fn.as_ref().map(|_| "Fn"))
+            .field("population_scale", &self.population_scale)
+            .field("population_reference", &self.population_reference)
+            .field("fallback", &self.fallback)
+            .field("relax_steps", &self.relax_steps)
+            .field("relax_step_size", &self.relax_step_size)
+            .finish()
+    }
+}
+
+/// How long each phase of generating a palette took. Returned by
+/// [`PaletteBuilder::generate_with_timings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timings {
+    /// Shrinking the image and collecting the considered pixels from it.
+    pub resize: Duration,
+    /// Building the histogram of the considered pixels' colors.
+    pub histogram: Duration,
+    /// Quantizing the histogram into swatches.
+    pub split: Duration,
+    /// Processing the swatches further and selecting swatches for the targets.
+    pub scoring: Duration,
+}
+
+impl Timings {
+    /// Returns the total duration of all the phases.
+    pub fn total(&self) -> Duration {
+        self.resize + self.histogram + self.split + self.scoring
+    }
+}
+
+/// Statistics about how an image was quantized into swatches. Returned by
+/// [`PaletteBuilder::generate_with_stats`].
+///
+/// These help explain why a palette has fewer swatches than requested. For example, an image with
+/// only 9 distinct colors can't be quantized into 16 swatches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QuantizationStats {
+    /// The maximum amount of colors that was requested.
+    pub requested_colors: usize,
+    /// The amount of distinct quantized colors in the image's histogram, before filtering.
+    pub histogram_colors: usize,
+    /// The amount of colors the quantizer produced, before the final filtering of swatches.
+    pub quantized_colors: usize,
+    /// Whether quantization stopped before reaching the requested amount of colors because there
+    /// were no more colors to split.
+    pub terminated_early: bool,
+}
+
+/// The swatch to select for a target when no swatch is within the target's saturation and
+/// lightness ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TargetFallback {
+    /// Don't select a swatch for the target.
+    #[default]
+    None,
+    /// Select the dominant swatch, which is the swatch with the largest population.
+    Dominant,
+    /// Select the swatch whose saturation and lightness are nearest to the target's.
+    Nearest,
+}
+
+/// The scale a swatch's population is compared to the dominant swatch's population on when scoring
+/// swatches for targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PopulationScale {
+    /// Compare the populations directly.
+    #[default]
+    Linear,
+    /// Compare the logarithms of the populations. This makes the population score less dependent on
+    /// the size of the swatch, so small but vivid swatches aren't crowded out by large flat
+    /// backgrounds.
+    Log,
+}
+
+/// The swatch whose population a swatch's population is compared to when scoring swatches for
+/// targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PopulationReference {
+    /// Compare to the dominant swatch of the whole palette.
+    #[default]
+    GlobalDominant,
+    /// Compare to the dominant swatch among the swatches that can be selected for the target. This
+    /// keeps a large swatch that's outside the target's ranges, such as a background, from pushing
+    /// down the population scores of every swatch that's within them.
+    InRangeDominant,
 }
 
 impl Palette {
@@ -82,11 +252,48 @@ impl Palette {
         &self.swatches
     }
 
+    /// Returns the swatches in this palette sorted by their relative luminance, from the darkest to
+    /// the lightest.
+    ///
+    /// Relative luminance (see [`Swatch::relative_luminance`]) weighs the channels by how bright
+    /// they appear, so unlike sorting by HSL lightness this gives perceptually ordered ramps, such
+    /// as for light-to-dark UI scales.
+    pub fn swatches_sorted_by_luminance(&self) -> Vec<Swatch> {
+        let mut swatches = self.swatches.clone();
+        swatches.sort_by(|lhs, rhs| lhs.relative_luminance().total_cmp(&rhs.relative_luminance()));
+        swatches
+    }
+
+    /// Returns the number of swatches in this palette.
+    pub fn len(&self) -> usize {
+        self.swatches.len()
+    }
+
+    /// Returns whether this palette has no swatches.
+    pub fn is_empty(&self) -> bool {
+        self.swatches.is_empty()
+    }
+
+    /// Returns the swatches the filters rejected after quantizing, if this palette was generated
+    /// with [`PaletteBuilder::keep_rejected_swatches`] enabled. Otherwise, this is empty.
+    pub fn rejected_swatches(&self) -> &[Swatch] {
+        &self.rejected_swatches
+    }
+
     /// Returns the targets in this palette.
     pub fn targets(&self) -> &[Target] {
         &self.targets
     }
 
+    /// Returns an iterator over the targets in this palette along with the swatch selected for
+    /// each, in the order of [`Palette::targets`]. Targets without a selected swatch are included
+    /// with `None`.
+    pub fn target_selections(&self) -> impl Iterator<Item = (&Target, Option<Swatch>)> + '_ {
+        self.targets
+            .iter()
+            .map(|target| (target, self.get_swatch_for_target(*target)))
+    }
+
     /// Returns the swatch corresponding to the preset light vibrant target, if it exists.
     pub fn light_vibrant_swatch(&self) -> Option<Swatch> {
         self.get_swatch_for_target(Target::light_vibrant())
@@ -157,6 +364,40 @@ impl Palette {
         self.selected_swatches.get(&target.id()).copied().flatten()
     }
 
+    /// Returns the swatch corresponding to the preset target with the given name, if it exists.
+    ///
+    /// The names are the preset names in snake case: `"light_vibrant"`, `"vibrant"`,
+    /// `"dark_vibrant"`, `"light_muted"`, `"muted"` and `"dark_muted"`. This is useful when the
+    /// target is chosen by a string, such as from a configuration file. Unknown names return
+    /// `None`.
+    pub fn swatch_by_name(&self, name: &str) -> Option<Swatch> {
+        let preset = PresetTarget::from_key(name)?;
+        self.get_swatch_for_target(preset.target())
+    }
+
+    /// Returns the swatch of the first preset target in the given order that has a swatch, if any
+    /// of them do.
+    ///
+    /// This is the common fallback pattern of, for example, using the vibrant swatch if there is
+    /// one and the muted swatch otherwise: `palette.first_available(&[PresetTarget::Vibrant,
+    /// PresetTarget::Muted])`.
+    pub fn first_available(&self, presets: &[PresetTarget]) -> Option<Swatch> {
+        presets
+            .iter()
+            .find_map(|preset| self.get_swatch_for_target(preset.target()))
+    }
+
+    /// Returns whether a swatch was selected for a given target.
+    pub fn has_swatch_for_target(&self, target: Target) -> bool {
+        matches!(self.selected_swatches.get(&target.id()), Some(Some(_)))
+    }
+
+    /// Returns whether the swatch selected for a given target is a fallback, meaning no swatch was
+    /// within the target's ranges. See [`PaletteBuilder::target_fallback`].
+    pub fn is_fallback(&self, target: Target) -> bool {
+        self.fallback_targets.contains(&target.id())
+    }
+
     /// Returns the most prominent color in the palette, which is the swatch with the largest
     /// population.
     pub fn most_prominent_color(&self) -> Option<(u8, u8, u8)> {
@@ -165,6 +406,392 @@ impl Palette {
             .max_by_key(|swatch| swatch.population())
             .map(|swatch| swatch.rgb())
     }
+
+    /// Score the palette's swatches for its targets again, replacing the selected swatches.
+    ///
+    /// This is mainly useful for palettes that have been deserialized, whose selected swatches may
+    /// be stale or missing. The swatches are scored with the default scoring; a custom scoring
+    /// function or population scale set on the builder that generated the palette isn't retained.
+    pub fn recompute_selected(&mut self) {
+        self.scoring = ScoringOptions::default();
+        (self.selected_swatches, self.fallback_targets) =
+            select_swatches(&self.swatches, &mut self.targets, &self.scoring);
+    }
+
+    /// Returns a new palette with the same swatches, but with swatches selected for the given
+    /// targets instead of the palette's targets.
+    ///
+    /// This skips quantizing the image again, which makes trying out different targets fast. As
+    /// with [`Palette::recompute_selected`], the swatches are scored with the default scoring.
+    pub fn rescore(&self, mut targets: Vec<Target>) -> Palette {
+        let (selected_swatches, fallback_targets) =
+            select_swatches(&self.swatches, &mut targets, &ScoringOptions::default());
+
+        Palette {
+            swatches: self.swatches.clone(),
+            targets,
+            selected_swatches,
+            fallback_targets,
+            rejected_swatches: self.rejected_swatches.clone(),
+            scoring: ScoringOptions::default(),
+        }
+    }
+
+    /// Returns every swatch in the palette along with its score for a given target and whether it
+    /// could be selected for the target, for inspecting why a swatch was or wasn't selected.
+    ///
+    /// A swatch can be selected if its saturation and lightness are within the target's ranges and
+    /// it isn't already selected for a target before the given one in the palette, since a swatch
+    /// is selected for one target at most. The selected swatch is the one with the highest score
+    /// among the swatches that can be selected, unless the target was relaxed or fell back to
+    /// another swatch. The swatches are scored the same way they were when selecting them, with the
+    /// scoring settings of the builder that generated the palette, such as its score function,
+    /// population scale and population reference. Those settings aren't serialized, so the
+    /// swatches of a deserialized palette are scored with the default scoring, like
+    /// [`Palette::recompute_selected`] does.
+    pub fn score_breakdown(&self, mut target: Target) -> Vec<(Swatch, f32, bool)> {
+        target.normalize_weights();
+
+        // the colors selected for the targets before the given one, skipping fallbacks just like
+        // when the swatches are selected
+        let used_colors: HashSet<_> = self
+            .targets
+            .iter()
+            .take_while(|other| other.id() != target.id())
+            .filter(|other| !self.is_fallback(**other))
+            .filter_map(|other| self.get_swatch_for_target(*other))
+            .map(|swatch| swatch.rgb())
+            .collect();
+
+        let dominant_swatch = self.scoring.reference_swatch(&self.swatches, target, &used_colors);
+
+        self.swatches
+            .iter()
+            .map(|&swatch| {
+                let score = self.scoring.score(swatch, dominant_swatch, target);
+                let selectable = should_be_scored_for_target(swatch, target, &used_colors);
+                (swatch, score, selectable)
+            })
+            .collect()
+    }
+
+    /// Returns a new palette blending this palette's selected swatches with another's by the
+    /// given amount, where 0 gives this palette's colors and 1 gives the other's.
+    ///
+    /// The swatches selected for the same target in both palettes, such as the vibrant swatches,
+    /// are paired up and their colors and populations interpolated linearly in sRGB. A swatch
+    /// selected in only one of the palettes is passed through unchanged. The blended palette has
+    /// the targets of both palettes, and its swatches are the blended selections. This is useful
+    /// for crossfading between the themes of two images.
+    pub fn blend(&self, other: &Palette, t: f32) -> Palette {
+        let t = t.clamp(0.0, 1.0);
+        let mut targets = self.targets.clone();
+        targets.extend(other.targets.iter().filter(|target| !self.targets.contains(target)));
+
+        let mut swatches: Vec<Swatch> = Vec::new();
+        let mut selected_swatches = BTreeMap::new();
+        let mut fallback_targets = BTreeSet::new();
+
+        for target in targets.iter().copied() {
+            let selected = match (
+                self.get_swatch_for_target(target),
+                other.get_swatch_for_target(target),
+            ) {
+                (Some(from), Some(to)) => Some(blend_swatches(from, to, t)),
+                (from, to) => from.or(to),
+            };
+
+            if let Some(swatch) = selected {
+                if !swatches.contains(&swatch) {
+                    swatches.push(swatch);
+                }
+
+                if self.is_fallback(target) || other.is_fallback(target) {
+                    fallback_targets.insert(target.id());
+                }
+            }
+
+            selected_swatches.insert(target.id(), selected);
+        }
+
+        Palette {
+            swatches,
+            targets,
+            selected_swatches,
+            fallback_targets,
+            rejected_swatches: Vec::new(),
+            scoring: ScoringOptions::default(),
+        }
+    }
+
+    /// Returns the dominant hue of the palette in degrees, if the palette has any chromatic
+    /// swatches.
+    ///
+    /// The dominant hue is the circular mean of the swatch hues, weighted by both the swatch
+    /// population and saturation so achromatic swatches contribute little or nothing. The hues are
+    /// averaged as unit vectors to handle the wraparound at 360 degrees correctly.
+    pub fn dominant_hue(&self) -> Option<f32> {
+        let (x, y) = self.swatches.iter().fold((0.0, 0.0), |(x, y), swatch| {
+            let (h, s, _) = swatch.hsl();
+            let weight = swatch.population() as f32 * s;
+            let h = h.to_radians();
+
+            (x + h.cos() * weight, y + h.sin() * weight)
+        });
+
+        if x == 0.0 && y == 0.0 {
+            return None;
+        }
+
+        Some(y.atan2(x).to_degrees().rem_euclid(360.0))
+    }
+
+    /// Returns the color temperature of the palette, from -1 for entirely cool to 1 for entirely
+    /// warm.
+    ///
+    /// Each swatch counts as warm or cool based on [`Swatch::is_warm`], weighted by its population.
+    /// Achromatic swatches count as neutral. An empty palette has a temperature of 0.
+    pub fn temperature(&self) -> f32 {
+        let (sum, total) = self.swatches.iter().fold((0.0, 0.0), |(sum, total), swatch| {
+            let population = swatch.population() as f32;
+            (sum + swatch.temperature() * population, total + population)
+        });
+
+        if total > 0.0 {
+            sum / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the mean color of the palette's swatches weighted by their population, if the
+    /// palette has any swatches with a population.
+    ///
+    /// The colors are averaged in linear light, since averaging sRGB values biases the result
+    /// towards darker colors. For example, equal amounts of black and white average to the sRGB
+    /// gray 188 instead of 128. Unlike the dominant swatch, the mean color may not be in the
+    /// palette at all.
+    pub fn mean_color(&self) -> Option<(u8, u8, u8)> {
+        let (sum, total) = self.swatches.iter().fold(
+            ((0.0, 0.0, 0.0), 0.0),
+            |((r, g, b), total), swatch| {
+                let population = swatch.population() as f64;
+                let (lr, lg, lb) = swatch.rgb_linear();
+                let (lr, lg, lb) = (lr as f64, lg as f64, lb as f64);
+
+                (
+                    (r + lr * population, g + lg * population, b + lb * population),
+                    total + population,
+                )
+            },
+        );
+
+        if total == 0.0 {
+            return None;
+        }
+
+        let channel = |sum: f64| color::linear_to_srgb((sum / total) as f32);
+        Some((channel(sum.0), channel(sum.1), channel(sum.2)))
+    }
+
+    /// Returns the colors selected for the preset targets along with the names of the presets, in
+    /// the order of [`Target::default_targets`].
+    ///
+    /// The names are the same as those accepted by [`Palette::swatch_by_name`], such as
+    /// `"light_vibrant"`. Only the presets with a selected swatch are included.
+    pub fn named_colors(&self) -> Vec<(String, (u8, u8, u8))> {
+        PresetTarget::ALL
+            .into_iter()
+            .filter_map(|preset| {
+                let swatch = self.get_swatch_for_target(preset.target())?;
+                Some((preset.key().to_string(), swatch.rgb()))
+            })
+            .collect()
+    }
+
+    /// Returns the colors selected for the preset targets as design tokens in JSON, in the format
+    /// used by tools such as Style Dictionary and Figma Tokens.
+    ///
+    /// The tokens are grouped under the given namespace and named after the presets, such as
+    /// `{"color":{"vibrant":{"value":"#c81010","type":"color"}}}` for the namespace `color`. Only
+    /// the presets with a selected swatch are included.
+    pub fn to_design_tokens(&self, namespace: &str) -> String {
+        let tokens: Vec<_> = PresetTarget::ALL
+            .into_iter()
+            .filter_map(|preset| {
+                let swatch = self.get_swatch_for_target(preset.target())?;
+                Some(format!(
+                    r#""{}":{{"value":"{}","type":"color"}}"#,
+                    preset.key(),
+                    swatch.hex()
+                ))
+            })
+            .collect();
+
+        format!(r#"{{"{}":{{{}}}}}"#, escape_json(namespace), tokens.join(","))
+    }
+
+    /// Returns a CSS `linear-gradient()` of the palette's swatches at the given angle in degrees,
+    /// such as `linear-gradient(90deg, #aa0000 0%, #00bb00 50%, #0000cc 100%)`.
+    ///
+    /// The swatches are ordered by their population, the most prominent first, and their color
+    /// stops are spaced evenly across the gradient. A palette with a single swatch gives a solid
+    /// gradient of its color, and an empty palette gives a transparent gradient.
+    pub fn to_css_gradient(&self, angle_deg: f32) -> String {
+        let mut swatches = self.swatches.clone();
+        swatches.sort_by_key(|swatch| std::cmp::Reverse(swatch.population()));
+
+        let colors: Vec<_> = match swatches.as_slice() {
+            [] => vec![String::from("transparent"); 2],
+            [swatch] => vec![swatch.hex(); 2],
+            swatches => swatches.iter().map(|swatch| swatch.hex()).collect(),
+        };
+
+        let last = (colors.len() - 1) as f32;
+        let stops: Vec<_> = colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let position = (i as f32 / last * 10000.0).round() / 100.0;
+                format!("{color} {position}%")
+            })
+            .collect();
+
+        format!("linear-gradient({angle_deg}deg, {})", stops.join(", "))
+    }
+
+    /// Returns a subset of the palette's swatches that stay distinguishable from each other to
+    /// someone with a given color vision deficiency.
+    ///
+    /// The swatches are picked greedily in order of population, the most prominent first. A swatch
+    /// is picked if its simulated color (see [`Swatch::simulate`]) is at least `min_distance` away
+    /// from the simulated colors of every swatch picked so far, measured as the Euclidean distance
+    /// in CIELAB. This is useful for picking chart colors for color-blind audiences.
+    pub fn colorblind_safe_set(&self, kind: ColorBlindness, min_distance: f32) -> Vec<Swatch> {
+        let mut swatches = self.swatches.clone();
+        swatches.sort_by_key(|swatch| std::cmp::Reverse(swatch.population()));
+
+        let mut picked: Vec<(Swatch, (f32, f32, f32))> = Vec::new();
+        for swatch in swatches {
+            let lab = color::rgb_to_lab(swatch.simulate(kind));
+            let is_distinguishable = picked
+                .iter()
+                .all(|&(_, other)| color::lab_distance(lab, other) >= min_distance);
+
+            if is_distinguishable {
+                picked.push((swatch, lab));
+            }
+        }
+
+        picked.into_iter().map(|(swatch, _)| swatch).collect()
+    }
+
+    /// Returns the quantization error of the palette for a given image, which is the mean
+    /// perceptual distance from each pixel in the image to its nearest swatch.
+    ///
+    /// The distance is the Euclidean distance in CIELAB (the CIE76 color difference), where a
+    /// distance of roughly 2.3 is just noticeable. The error is useful for measuring how well the
+    /// palette represents the image, for example when tuning the maximum color count. The image
+    /// should be the one the palette was generated from. An empty image has an error of 0, while an
+    /// empty palette has an infinite error for any non-empty image.
+    pub fn quantization_error<P>(&self, image: &ImageBuffer<P, Vec<u8>>) -> f32
+    where
+        P: image::Pixel<Subpixel = u8>,
+    {
+        let swatch_colors: Vec<_> = self
+            .swatches
+            .iter()
+            .map(|swatch| color::rgb_to_lab(swatch.rgb()))
+            .collect();
+
+        // images tend to have far fewer distinct colors than pixels, so cache the distances
+        let mut distances = HashMap::new();
+        let mut sum = 0.0;
+        let mut count = 0;
+
+        for pixel in image.pixels() {
+            let rgb = pixel.rgb();
+            let distance = *distances.entry(rgb).or_insert_with(|| {
+                let lab = color::rgb_to_lab(rgb);
+                swatch_colors
+                    .iter()
+                    .map(|&swatch_lab| color::lab_distance(lab, swatch_lab))
+                    .fold(f32::INFINITY, f32::min)
+            });
+
+            sum += distance as f64;
+            count += 1;
+        }
+
+        if count > 0 {
+            (sum / count as f64) as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Eq for Palette {}
+impl PartialEq for Palette {
+    /// Palettes are equal if they have the same swatches in any order, and the same swatches
+    /// selected for the same targets. Since targets are identified by their ID, the targets
+    /// themselves are compared only through the selected swatches.
+    fn eq(&self, other: &Self) -> bool {
+        fn sorted(swatches: &[Swatch]) -> Vec<Swatch> {
+            let mut swatches = swatches.to_vec();
+            swatches.sort_by_key(|swatch| (swatch.rgb(), swatch.population()));
+            swatches
+        }
+
+        self.selected_swatches == other.selected_swatches
+            && self.fallback_targets == other.fallback_targets
+            && sorted(&self.swatches) == sorted(&other.swatches)
+    }
+}
+
+impl PaletteBuilder<Rgb<u8>> {
+    /// Returns a new [`PaletteBuilder`] from an image file at the given path.
+    ///
+    /// The image's format is guessed from its extension and contents, and the decoded image is
+    /// converted into 8-bit RGB. Any error opening or decoding the image is returned.
+    pub fn open<Q>(path: Q) -> Result<Self, image::ImageError>
+    where
+        Q: AsRef<std::path::Path>,
+    {
+        let image = image::ImageReader::open(path)?.with_guessed_format()?.decode()?;
+        Ok(Self::from_image(image.to_rgb8()))
+    }
+
+    /// Returns a new [`PaletteBuilder`] from a high dynamic range image, such as a decoded EXR or
+    /// Radiance HDR image.
+    ///
+    /// The image's values are taken to be in linear light, where 1 is the nominal white and
+    /// brighter values are allowed. They are mapped onto the 0 to 1 range with the given tone
+    /// mapping operator and encoded into 8-bit sRGB, and the resulting image is quantized like any
+    /// other.
+    pub fn from_hdr_image(image: image::Rgb32FImage, tone_mapping: ToneMapping) -> Self {
+        let image = ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let Rgb(channels) = *image.get_pixel(x, y);
+            Rgb(channels.map(|value| color::linear_to_srgb(tone_mapping.map(value))))
+        });
+
+        Self::from_image(image)
+    }
+
+    /// Returns a new [`PaletteBuilder`] from precomputed colors and their pixel counts, skipping
+    /// the image entirely.
+    ///
+    /// This is useful when the color counts are already known, for example from a GPU reduction
+    /// or a previous run. The colors are fed directly into the quantizer's histogram, so they are
+    /// filtered and quantized like an image's colors would be. The settings that concern the image,
+    /// such as the region, resizing, sampling and background exclusion, have no effect. Swatch
+    /// populations are capped at [`u32::MAX`] when the counts add up past it.
+    pub fn from_weighted_colors(colors: Vec<((u8, u8, u8), u32)>) -> Self {
+        Self {
+            weighted_colors: colors,
+            ..Self::from_image(ImageBuffer::new(0, 0))
+        }
+    }
 }
 
 impl<P> PaletteBuilder<P>
@@ -179,7 +806,51 @@ where
             maximum_color_count: DEFAULT_CALCULATE_NUMBER_COLORS,
             resize_area: Some(DEFAULT_RESIZE_IMAGE_AREA),
             region: None,
-            filters: vec![Box::new(DefaultFilter)],
+            filters: Vec::new(),
+            use_default_filter: true,
+            minimum_saturation: None,
+            grayscale: matches!(P::COLOR_MODEL, "Y" | "YA"),
+            quantizer_options: QuantizerOptions::default(),
+            minimum_population_fraction: 0.0,
+            minimum_swatch_population: 0,
+            keep_rejected_swatches: false,
+            exclude_background: false,
+            premultiplied: false,
+            merge_hsl: None,
+            sample_stride: 1,
+            sample_count: None,
+            sample_seed: None,
+            weighted_colors: Vec::new(),
+            scoring: ScoringOptions::default(),
+        }
+    }
+
+    /// Returns a new [`PaletteBuilder`] from a given image view, such as a [`image::SubImage`] of a
+    /// larger image.
+    ///
+    /// The view's pixels are counted straight into the quantizer's histogram, like the colors given
+    /// to [`PaletteBuilder::from_weighted_colors`], so they're never copied into an image buffer of
+    /// their own. Because of that, the settings that concern the image, such as the region,
+    /// resizing, sampling, background exclusion and alpha weighting, have no effect. Resizing needs
+    /// a buffer of the pixels, so to shrink the view before quantizing, copy it into one, such as
+    /// with [`image::SubImage::to_image`], and use [`PaletteBuilder::from_image`] instead.
+    pub fn from_view<V>(view: &V) -> Self
+    where
+        V: GenericImageView<Pixel = P>,
+    {
+        let mut counts: HashMap<(u8, u8, u8), u32> = HashMap::new();
+        for (_, _, pixel) in view.pixels() {
+            let count = counts.entry(pixel.rgb()).or_default();
+            *count = count.saturating_add(1);
+        }
+
+        // sorted so the same view always gives the same histogram order
+        let mut weighted_colors: Vec<_> = counts.into_iter().collect();
+        weighted_colors.sort_unstable();
+
+        Self {
+            weighted_colors,
+            ..Self::from_image(ImageBuffer::new(0, 0))
         }
     }
 
@@ -187,6 +858,56 @@ where
         unimplemented!()
     }
 
+    /// Set the maximum amount of colors to quantize the image into, which is the maximum amount of
+    /// swatches in the palette.
+    ///
+    /// By default the image will be quantized into at most 16 colors, as defined in the
+    /// [`DEFAULT_CALCULATE_NUMBER_COLORS`] constant.
+    pub fn maximum_color_count(self, maximum_color_count: usize) -> Self {
+        Self {
+            maximum_color_count,
+            ..self
+        }
+    }
+
+    /// Set the stride to sample the considered pixels with. Only every Nth pixel is considered.
+    ///
+    /// Sampling trades accuracy for speed, which is mostly useful for very large images that
+    /// aren't shrunk with [`PaletteBuilder::resize_image_area`]. The swatch populations are
+    /// multiplied by the stride so they stay comparable to the populations without sampling. By
+    /// default every pixel is considered. A stride of 0 is treated as 1.
+    pub fn sample_stride(self, sample_stride: usize) -> Self {
+        Self {
+            sample_stride: sample_stride.max(1),
+            ..self
+        }
+    }
+
+    /// Set the maximum amount of considered pixels to randomly sample. Set to `None` to disable
+    /// random sampling.
+    ///
+    /// If there are more considered pixels than the given amount, that many of them are chosen at
+    /// random with reservoir sampling, which bounds the quantization work regardless of the image
+    /// size. The swatch populations are scaled so they stay comparable to the populations without
+    /// sampling. The sampling is random on every run unless a seed is set with
+    /// [`PaletteBuilder::sample_seed`]. By default, the pixels are not randomly sampled.
+    pub fn sample_count(self, sample_count: Option<usize>) -> Self {
+        Self {
+            sample_count,
+            ..self
+        }
+    }
+
+    /// Set the seed to randomly sample the considered pixels with, making the sampling
+    /// reproducible. Set to `None` to sample differently on every run. See
+    /// [`PaletteBuilder::sample_count`].
+    pub fn sample_seed(self, sample_seed: Option<u64>) -> Self {
+        Self {
+            sample_seed,
+            ..self
+        }
+    }
+
     /// Set the desired area to shrink the image to before quantizing. Set to `None` to disable
     /// shrinking.
     ///
@@ -204,20 +925,45 @@ where
     ///
     /// The region is based on the original image. If the image is shrunk before quantizing (see
     /// [`PaletteBuilder::resize_image_area`]), the given region will be scaled accordingly to still
-    /// cover a similar area in the shrunk image. By default, the entire image is used to
-    /// generate the palette.
+    /// cover a similar area in the shrunk image. A region extending past the image's edges is
+    /// truncated to the image. By default, the entire image is used to generate the palette.
     pub fn region(self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        let (image_width, image_height) = self.image.dimensions();
+        let x = x.min(image_width);
+        let y = y.min(image_height);
+
         Self {
             region: Some(Rect {
                 x,
                 y,
-                width,
-                height,
+                width: width.min(image_width - x),
+                height: height.min(image_height - y),
             }),
             ..self
         }
     }
 
+    /// Set a custom region to focus the palette generation on, given in fractions of the image's
+    /// dimensions from 0 to 1.
+    ///
+    /// The fractions are multiplied by the original image's dimensions and rounded to the nearest
+    /// pixel, and the region is clamped to the image's bounds. For example, `(0.25, 0.25, 0.5,
+    /// 0.5)` is the centered region covering half of the image's width and height. See
+    /// [`PaletteBuilder::region`].
+    pub fn region_normalized(self, x: f32, y: f32, width: f32, height: f32) -> Self {
+        let (image_width, image_height) = self.image.dimensions();
+        let scale = |fraction: f32, length: u32| {
+            (fraction.clamp(0.0, 1.0) * length as f32).round() as u32
+        };
+
+        self.region(
+            scale(x, image_width),
+            scale(y, image_height),
+            scale(width, image_width),
+            scale(height, image_height),
+        )
+    }
+
     /// Add a custom target to the palette.
     ///
     /// By default, a set of preset targets are included in every palette. See
@@ -230,228 +976,2562 @@ where
         self
     }
 
+    /// Add multiple custom targets to the palette. Targets already in the palette are not added
+    /// again.
+    ///
+    /// See [`PaletteBuilder::add_target`].
+    pub fn add_targets<I>(self, targets: I) -> Self
+    where
+        I: IntoIterator<Item = Target>,
+    {
+        targets
+            .into_iter()
+            .fold(self, |builder, target| builder.add_target(target))
+    }
+
+    /// Remove a target from the palette, if it is in the palette. This can be used to remove
+    /// individual preset targets as well.
+    pub fn remove_target(mut self, target: Target) -> Self {
+        self.targets.retain(|existing| existing.id() != target.id());
+        self
+    }
+
+    /// Remove a preset target from the palette, if it is in the palette.
+    pub fn remove_preset(self, preset: PresetTarget) -> Self {
+        self.remove_target(preset.target())
+    }
+
     /// Add a custom filter to the palette. Multiple filters may be added. Filters will be evaluated
     /// in order of insertion.
     ///
-    /// A filter is used to reject certain colors from being included in the palette generation. A
-    /// [`DefaultFilter`] is included in every builder by default. It can be removed from the
-    /// builder with [`PaletteBuilder::clear_filters`].
+    /// A filter is used to reject certain colors from being included in the palette generation. The
+    /// filter is evaluated both on the image's colors before quantizing and on the average colors
+    /// of the quantized swatches. To only evaluate it in one of the stages, see
+    /// [`PaletteBuilder::add_input_filter`] and [`PaletteBuilder::add_output_filter`]. A
+    /// [`DefaultFilter`] is included in every builder by default and is evaluated before any custom
+    /// filters. It can be disabled with [`PaletteBuilder::use_default_filter`], or removed along
+    /// with all custom filters with [`PaletteBuilder::clear_filters`]. Filters have to be [`Send`],
+    /// like the scoring function set with [`PaletteBuilder::score_fn`].
     pub fn add_filter<F>(mut self, filter: F) -> Self
     where
-        F: Filter + 'static,
+        F: Filter + Send + 'static,
     {
-        self.filters.push(Box::new(filter));
+        self.filters.push(StagedFilter::new(Box::new(filter), true, true));
         self
     }
 
-    /// Clears the set region.
-    pub fn clear_region(self) -> Self {
-        Self {
-            region: None,
-            ..self
-        }
+    /// Add a custom filter evaluated only on the image's colors before quantizing. A color rejected
+    /// by the filter isn't quantized at all, but a swatch whose average color the filter would
+    /// reject is still included in the palette.
+    ///
+    /// See [`PaletteBuilder::add_filter`].
+    pub fn add_input_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Filter + Send + 'static,
+    {
+        self.filters.push(StagedFilter::new(Box::new(filter), true, false));
+        self
     }
 
-    /// Removes all targets in the builder, including the presets.
-    pub fn clear_targets(self) -> Self {
+    /// Add a custom filter evaluated only on the average colors of the quantized swatches. The
+    /// image's colors are quantized regardless of the filter, but swatches whose average color the
+    /// filter rejects are left out of the palette.
+    ///
+    /// See [`PaletteBuilder::add_filter`].
+    pub fn add_output_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Filter + Send + 'static,
+    {
+        self.filters.push(StagedFilter::new(Box::new(filter), false, true));
+        self
+    }
+
+    /// Add multiple custom filters to the palette. Filters will be evaluated in order of
+    /// insertion.
+    ///
+    /// See [`PaletteBuilder::add_filter`].
+    pub fn add_filters<I>(mut self, filters: I) -> Self
+    where
+        I: IntoIterator<Item = BoxedFilter>,
+    {
+        self.filters
+            .extend(filters.into_iter().map(|filter| StagedFilter::new(filter, true, true)));
+        self
+    }
+
+    /// Set a minimum HSL saturation in the range 0 to 1, rejecting every color below it. This is a
+    /// shorthand for ignoring grays and colors close to gray, such as a minimum of 0.2 for leaving
+    /// out pale grays while keeping vivid colors.
+    ///
+    /// Like a custom filter added with [`PaletteBuilder::add_filter`], the minimum is evaluated
+    /// both before and after quantizing. It's evaluated after the default filter and before any
+    /// custom filters, and removed with [`PaletteBuilder::clear_filters`]. By default, there is no
+    /// minimum saturation.
+    pub fn minimum_saturation(self, minimum_saturation: f32) -> Self {
         Self {
-            targets: Vec::new(),
+            minimum_saturation: Some(minimum_saturation),
             ..self
         }
     }
 
-    /// Removes all filters in the builder, including the default filter.
-    pub fn clear_filters(self) -> Self {
+    /// Set whether the [`DefaultFilter`] is used to reject colors, without affecting any custom
+    /// filters. By default, the default filter is used.
+    pub fn use_default_filter(self, enabled: bool) -> Self {
         Self {
-            filters: Vec::new(),
+            use_default_filter: enabled,
             ..self
         }
     }
 
-    /// Consume the builder and generate a new [`Palette`].
-    pub fn generate(mut self) -> Palette {
-        // scale down the image if requested
-        if self.scale_image_down() {
-            if let Some(mut region) = self.region {
-                // scale down the region to match the new scaled image
-                let scale = self.image.width() as f32 / self.image.height() as f32;
-
-                region.x = (region.x as f32 * scale).floor() as u32;
-                region.y = (region.y as f32 * scale).floor() as u32;
-                region.width = ((region.width as f32 * scale) as u32 + region.x)
-                    .min(self.image.width() - region.x);
-                region.height = ((region.height as f32 * scale) as u32 + region.y)
-                    .min(self.image.height() - region.y);
-
-                self.region = Some(region);
-            }
-        }
+    /// Set whether the image should be quantized in grayscale mode.
+    ///
+    /// In grayscale mode the image is quantized by bucketing its pixels by lightness instead of the
+    /// usual median cut, and the default filter is replaced by a filter with relaxed black and
+    /// white thresholds, since the default filter rejects many of the grays an image like this
+    /// consists of. Custom filters are evaluated as usual. Targets that accept unsaturated colors,
+    /// such as the muted presets, still match based on lightness. Fully transparent pixels are
+    /// skipped, so the opaque region of an image with an alpha mask is bucketed on its own. By
+    /// default, grayscale mode is enabled for grayscale images such as [`image::GrayImage`] and
+    /// [`image::GrayAlphaImage`].
+    pub fn grayscale_mode(self, grayscale: bool) -> Self {
+        Self { grayscale, ..self }
+    }
 
-        // get pixels in the requested region, or in the entire image
-        let pixels = if let Some(region) = self.region {
-            self.image
-                .view(region.x, region.y, region.width, region.height)
-                .pixels()
-                .map(|(_, _, p)| p)
+    /// Set the color space the image's colors are quantized in.
+    ///
+    /// By default the colors are quantized in sRGB with [`QuantizerKind::Rgb`]. See
+    /// [`QuantizerKind`] for the alternatives. Regardless of the color space, the resulting
+    /// swatches are always in sRGB.
+    pub fn quantizer_kind(mut self, kind: QuantizerKind) -> Self {
+        self.quantizer_options.kind = kind;
+        self
+    }
+
+    /// Set how the representative color of each quantized color box is chosen.
+    ///
+    /// By default the representative color is the mean of the box's colors with
+    /// [`Representative::Mean`], which may not be a color in the image. With
+    /// [`Representative::Median`], it's the median of the box's colors instead, which always is.
+    /// [`PaletteBuilder::linear_averaging`] only affects the mean.
+    pub fn representative_color(mut self, representative: Representative) -> Self {
+        self.quantizer_options.representative = representative;
+        self
+    }
+
+    /// Set whether the colors in each quantized color box should be averaged in linear light.
+    ///
+    /// By default the representative color of each box is the plain average of its sRGB colors.
+    /// Since sRGB is non-linear, this biases the swatches towards darker colors. When enabled, the
+    /// colors are instead converted to linear light, averaged and converted back to sRGB, which
+    /// gives perceptually truer swatches.
+    pub fn linear_averaging(mut self, enabled: bool) -> Self {
+        self.quantizer_options.linear_averaging = enabled;
+        self
+    }
+
+    /// Set how the red, green and blue channels are weighted when choosing which color box to split
+    /// during quantization, and along which channel.
+    ///
+    /// By default the channels are weighted uniformly with [`VolumeWeighting::Uniform`]. With
+    /// [`VolumeWeighting::Luma`], boxes spanning a wide range of greens are split before boxes
+    /// spanning an equally wide range of blues, since the eye is more sensitive to green. The
+    /// weighting only applies when quantizing in sRGB with [`QuantizerKind::Rgb`].
+    pub fn volume_weighting(mut self, weighting: VolumeWeighting) -> Self {
+        self.quantizer_options.volume_weighting = weighting;
+        self
+    }
+
+    /// Set which color boxes are split first during quantization.
+    ///
+    /// By default the box with the largest volume is split first with [`SplitPriority::Volume`].
+    /// With [`SplitPriority::VolumeTimesPopulation`], the box's population is accounted for as
+    /// well, so small but densely populated boxes can be split before large, sparse ones.
+    pub fn split_priority(mut self, priority: SplitPriority) -> Self {
+        self.quantizer_options.split_priority = priority;
+        self
+    }
+
+    /// Set where color boxes are split during quantization.
+    ///
+    /// By default boxes are split at the population median of their longest dimension with
+    /// [`SplitMethod::PopulationMedian`]. With [`SplitMethod::DimensionMidpoint`], they are split
+    /// at the spatial midpoint of the dimension instead, which gives more evenly sized boxes on
+    /// images with skewed color distributions.
+    pub fn split_method(mut self, method: SplitMethod) -> Self {
+        self.quantizer_options.split_method = method;
+        self
+    }
+
+    /// Set the amount of k-means refinement iterations to run after quantizing the image colors.
+    ///
+    /// Each iteration assigns every color in the image's color histogram to its nearest swatch and
+    /// moves each swatch to the population-weighted average of the colors assigned to it, updating
+    /// the swatch populations accordingly. A single iteration often makes the swatches more
+    /// representative of the image at little cost. By default, no refinement is done. Refinement
+    /// only applies when the image has more distinct colors than the maximum color count.
+    pub fn refine_swatches(mut self, iterations: u32) -> Self {
+        self.quantizer_options.refine_iterations = iterations;
+        self
+    }
+
+    /// Set whether the color histogram should be built in a deterministic order.
+    ///
+    /// The histogram of the image colors is normally built in a hash map and sorted afterwards.
+    /// When enabled, it is built in an ordered map keyed by the packed colors instead, so the
+    /// histogram is ordered the same way on every run from the very beginning, which makes palette
+    /// generation easier to reproduce and debug. This is slightly slower. By default, the histogram
+    /// isn't built deterministically.
+    pub fn deterministic(mut self, enabled: bool) -> Self {
+        self.quantizer_options.deterministic = enabled;
+        self
+    }
+
+    /// Set a seed to make palette generation reproducible.
+    ///
+    /// With a seed, every step of the generation that would otherwise vary between runs is made
+    /// deterministic: the color histogram is built in a deterministic order (see
+    /// [`PaletteBuilder::deterministic`]) and the random pixel sampling uses the seed (see
+    /// [`PaletteBuilder::sample_seed`]). Two generations with the same seed, image, settings and
+    /// targets then produce identical palettes. Note that targets created with [`Target::new`]
+    /// have random IDs, so the same target values have to be used in both generations.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.quantizer_options.deterministic = true;
+        self.sample_seed = Some(seed);
+        self
+    }
+
+    /// Set the minimum fraction of the total population a swatch must have to be included in the
+    /// palette.
+    ///
+    /// Swatches whose population is below the given fraction of the total population of all
+    /// swatches are dropped after quantizing, before the swatches are scored for targets. Unlike an
+    /// absolute population count, the fraction is independent of the image's resolution. By
+    /// default, no swatches are dropped.
+    pub fn minimum_population_fraction(self, minimum_population_fraction: f32) -> Self {
+        Self {
+            minimum_population_fraction,
+            ..self
+        }
+    }
+
+    /// Set the minimum population a swatch must have to be included in the palette.
+    ///
+    /// Swatches with fewer pixels than the given population are pruned from the palette after
+    /// quantizing, before the swatches are scored for targets, so neither [`Palette::swatches`]
+    /// nor the target selections include tiny clusters such as single-pixel artifacts. The
+    /// population is compared after accounting for sampling. By default, no swatches are pruned.
+    pub fn prune_swatches_below(self, population: u32) -> Self {
+        Self {
+            minimum_swatch_population: population,
+            ..self
+        }
+    }
+
+    /// Set whether the swatches rejected by the filters are kept in the palette.
+    ///
+    /// Normally swatches whose average color the filters reject are silently dropped after
+    /// quantizing. When enabled, they're instead available from [`Palette::rejected_swatches`],
+    /// which is useful for debugging filters. The rejected swatches are never scored for targets.
+    /// Colors the filters reject before quantizing never form swatches, so they aren't included;
+    /// see [`PaletteBuilder::add_output_filter`]. By default, rejected swatches aren't kept.
+    pub fn keep_rejected_swatches(self, enabled: bool) -> Self {
+        Self {
+            keep_rejected_swatches: enabled,
+            ..self
+        }
+    }
+
+    /// Set swatches with similar HSL hues and lightnesses to be merged together after quantizing.
+    ///
+    /// Two swatches are merged if their hues are within `hue_tolerance` degrees of each other and
+    /// their lightnesses within `lightness_tolerance` of each other. The merged swatch's color is
+    /// the population-weighted average of the two colors, and its population is their sum. This is
+    /// a cheap way to remove near-duplicate swatches. Since saturation isn't considered, grays
+    /// (whose hue is 0) may be merged with reds of similar lightness. By default, no swatches are
+    /// merged.
+    pub fn merge_similar_hsl(self, hue_tolerance: f32, lightness_tolerance: f32) -> Self {
+        Self {
+            merge_hsl: Some((hue_tolerance, lightness_tolerance)),
+            ..self
+        }
+    }
+
+    /// Set whether pixels close to the image's background color should be excluded from the palette
+    /// generation.
+    ///
+    /// The background color is detected as described in [`PaletteBuilder::detected_background`]. If
+    /// no consistent background color is detected, no pixels are excluded. By default, the
+    /// background is not excluded.
+    pub fn exclude_background(self, exclude_background: bool) -> Self {
+        Self {
+            exclude_background,
+            ..self
+        }
+    }
+
+    /// Set whether the image's colors have premultiplied alpha.
+    ///
+    /// Images with premultiplied alpha, such as many GPU textures, store their colors multiplied by
+    /// their alpha, which biases translucent pixels toward black and causes dark halos in the
+    /// palette. When enabled, each pixel's color is divided by its alpha before quantizing to
+    /// recover the original color, and fully transparent pixels are skipped. Images without an
+    /// alpha channel are unaffected. By default, colors are not assumed to be premultiplied.
+    pub fn premultiplied(self, premultiplied: bool) -> Self {
+        Self {
+            premultiplied,
+            ..self
+        }
+    }
+
+    /// Set a factor to boost the populations of saturated colors by before quantizing.
+    ///
+    /// Vivid accent colors often cover little of an image, so they get averaged into their
+    /// surroundings. With a boost, each color's population is multiplied by
+    /// `1 + factor * saturation` using its HSL saturation, which makes saturated colors more likely
+    /// to form their own swatches without discarding the neutral colors. The swatch populations
+    /// include the boost. By default, there is no boost.
+    pub fn saturation_boost(mut self, factor: f32) -> Self {
+        self.quantizer_options.saturation_boost = factor;
+        self
+    }
+
+    /// Set whether each pixel's population is weighted by its alpha.
+    ///
+    /// By default every pixel counts as one toward its color's population regardless of its alpha.
+    /// When enabled, each pixel counts as its alpha divided by 255 instead, so swatch populations
+    /// reflect only opaque coverage and fully transparent pixels contribute nothing. This is useful
+    /// for images with large transparent regions. Images without an alpha channel are unaffected.
+    pub fn weight_by_alpha(mut self, enabled: bool) -> Self {
+        self.quantizer_options.weight_by_alpha = enabled;
+        self
+    }
+
+    /// Set a custom function used to score swatches for targets.
+    ///
+    /// The function is given a swatch to score, the dominant swatch in the palette (the swatch with
+    /// the largest population), if there is one, and the target to score the swatch for. The
+    /// swatch with the highest score among the swatches within the target's saturation and
+    /// lightness ranges is selected for the target. By default, swatches are scored with a weighted
+    /// sum of how close their saturation, lightness and population are to the target's.
+    pub fn score_fn<F>(mut self, score_fn: F) -> Self
+    where
+        F: Fn(Swatch, Option<Swatch>, Target) -> f32 + Send + Sync + 'static,
+    {
+        self.scoring.score_fn = Some(Arc::new(score_fn));
+        self
+    }
+
+    /// Set targets that no swatch is within the ranges of to be retried with relaxed ranges.
+    ///
+    /// If no swatch is found for a target, the target's saturation and lightness ranges are widened
+    /// by `step_size` in both directions and the swatches are scored again, up to `steps` times.
+    /// This helps find reasonable swatches for the preset targets in low-contrast images. By
+    /// default, targets are not relaxed. Relaxing happens before falling back to another swatch
+    /// with [`PaletteBuilder::target_fallback`].
+    pub fn relax_unmatched_targets(mut self, steps: u32, step_size: f32) -> Self {
+        self.scoring.relax_steps = steps;
+        self.scoring.relax_step_size = step_size;
+        self
+    }
+
+    /// Set the swatch to fall back to for targets that no swatch is within the ranges of.
+    ///
+    /// By default, no swatch is selected for such targets. Whether a target's swatch is a fallback
+    /// can be checked with [`Palette::is_fallback`]. See [`TargetFallback`] for the alternatives.
+    pub fn target_fallback(mut self, fallback: TargetFallback) -> Self {
+        self.scoring.fallback = fallback;
+        self
+    }
+
+    /// Set the scale swatch populations are compared on when scoring swatches for targets.
+    ///
+    /// By default the populations are compared linearly with [`PopulationScale::Linear`]. See
+    /// [`PopulationScale`] for the alternatives. This has no effect if a custom scoring function is
+    /// set with [`PaletteBuilder::score_fn`].
+    pub fn population_scale(mut self, population_scale: PopulationScale) -> Self {
+        self.scoring.population_scale = population_scale;
+        self
+    }
+
+    /// Set the swatch whose population swatch populations are compared to when scoring swatches
+    /// for targets.
+    ///
+    /// By default the populations are compared to the dominant swatch of the whole palette with
+    /// [`PopulationReference::GlobalDominant`]. See [`PopulationReference`] for the alternatives.
+    /// The reference swatch is also the dominant swatch given to a custom scoring function set
+    /// with [`PaletteBuilder::score_fn`].
+    pub fn population_reference(mut self, population_reference: PopulationReference) -> Self {
+        self.scoring.population_reference = population_reference;
+        self
+    }
+
+    /// Clears the set region.
+    pub fn clear_region(self) -> Self {
+        Self {
+            region: None,
+            ..self
+        }
+    }
+
+    /// Removes all targets in the builder, including the presets.
+    pub fn clear_targets(self) -> Self {
+        Self {
+            targets: Vec::new(),
+            ..self
+        }
+    }
+
+    /// Removes all filters in the builder, including the default filter.
+    pub fn clear_filters(self) -> Self {
+        Self {
+            filters: Vec::new(),
+            use_default_filter: false,
+            minimum_saturation: None,
+            ..self
+        }
+    }
+
+    /// Consume the builder and generate a new [`Palette`].
+    pub fn generate(self) -> Palette {
+        self.generate_with_report().0
+    }
+
+    /// Consume the builder and generate a new [`Palette`], along with how long each phase of the
+    /// generation took. See [`Timings`] for the phases.
+    pub fn generate_with_timings(self) -> (Palette, Timings) {
+        let (palette, timings, _) = self.generate_with_report();
+        (palette, timings)
+    }
+
+    /// Consume the builder and generate a new [`Palette`], along with statistics about how the
+    /// image was quantized. See [`QuantizationStats`].
+    pub fn generate_with_stats(self) -> (Palette, QuantizationStats) {
+        let (palette, _, stats) = self.generate_with_report();
+        (palette, stats)
+    }
+
+    fn generate_with_report(mut self) -> (Palette, Timings, QuantizationStats) {
+        let mut timings = Timings::default();
+        let mut stats = QuantizationStats::default();
+
+        let start = Instant::now();
+        let (pixels, population_scale) = self.considered_pixels();
+        timings.resize = start.elapsed();
+
+        // quantize pixels, get swatches
+        let (mut swatches, mut rejected_swatches) = if self.grayscale {
+            let quantizer =
+                LightnessQuantizer::new(pixels, self.maximum_color_count, self.take_filters())
+                    .with_weighted_colors(self.weighted_colors)
+                    .with_weight_by_alpha(self.quantizer_options.weight_by_alpha);
+            quantizer.get_quantized_colors(&mut timings, &mut stats)
+        } else {
+            let quantizer = ColorCutQuantizer::new(
+                pixels,
+                self.maximum_color_count,
+                self.take_filters(),
+                self.quantizer_options,
+            )
+            .with_weighted_colors(self.weighted_colors);
+            quantizer.get_quantized_colors(&mut timings, &mut stats)
+        };
+
+        let start = Instant::now();
+
+        if !self.keep_rejected_swatches {
+            rejected_swatches.clear();
+        }
+
+        // account for the pixels skipped when sampling
+        if population_scale != 1.0 {
+            for swatch in swatches.iter_mut().chain(rejected_swatches.iter_mut()) {
+                let population = (swatch.population() as f32 * population_scale).round() as u32;
+                *swatch = Swatch::new(swatch.rgb(), population);
+            }
+        }
+
+        if let Some((hue_tolerance, lightness_tolerance)) = self.merge_hsl {
+            swatches = merge_similar_hsl(swatches, hue_tolerance, lightness_tolerance);
+        }
+
+        // drop swatches that cover too little of the image
+        if self.minimum_population_fraction > 0.0 {
+            let total_population: u64 =
+                swatches.iter().map(|swatch| swatch.population() as u64).sum();
+            let minimum_population = total_population as f32 * self.minimum_population_fraction;
+
+            swatches.retain(|swatch| swatch.population() as f32 >= minimum_population);
+        }
+
+        swatches.retain(|swatch| swatch.population() >= self.minimum_swatch_population);
+
+        // try to pick swatches for each target
+        let (selected_swatches, fallback_targets) =
+            select_swatches(&swatches, &mut self.targets, &self.scoring);
+
+        timings.scoring = start.elapsed();
+
+        let palette = Palette {
+            swatches,
+            targets: self.targets,
+            selected_swatches,
+            fallback_targets,
+            rejected_swatches,
+            scoring: self.scoring,
+        };
+
+        (palette, timings, stats)
+    }
+
+    /// Consume the builder and generate a new [`Palette`] without blocking the async executor.
+    ///
+    /// The palette is generated on Tokio's blocking thread pool with
+    /// [`tokio::task::spawn_blocking`], so this must be awaited from within a Tokio runtime. If
+    /// palette generation panics, the panic is propagated to the awaiting task.
+    #[cfg(feature = "tokio")]
+    pub async fn generate_async(self) -> Palette
+    where
+        P: Send,
+    {
+        match tokio::task::spawn_blocking(move || self.generate()).await {
+            Ok(palette) => palette,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    /// Consume the builder and generate a new [`Palette`] inside the given Rayon thread pool.
+    ///
+    /// With the `rayon` feature, the quantizer splits the image's colors in parallel when the image
+    /// has thousands of distinct colors and more colors are requested than the pool has threads,
+    /// and sorts large amounts of colors in parallel. The generation runs with
+    /// [`rayon::ThreadPool::install`], so this work is spread over the pool's threads instead of
+    /// Rayon's global pool. The palette is the same regardless of how many threads the pool has.
+    #[cfg(feature = "rayon")]
+    pub fn generate_in_pool(self, pool: &rayon::ThreadPool) -> Palette
+    where
+        P: Send,
+    {
+        pool.install(move || self.generate())
+    }
+
+    /// Consume the builder and return the histogram of quantized colors the palette would be
+    /// generated from.
+    ///
+    /// Each entry is a color and the amount of considered pixels that quantized into it. The colors
+    /// are quantized to 5 bits per channel and scaled back into 8 bits, and colors rejected by the
+    /// builder's filters are not included. In grayscale mode, the histogram is of the pixels'
+    /// lightness values instead, with each entry being a gray color. The histogram is returned
+    /// before any further quantization takes place.
+    pub fn histogram(mut self) -> Vec<((u8, u8, u8), u32)> {
+        let (pixels, _) = self.considered_pixels();
+        if self.grayscale {
+            let quantizer =
+                LightnessQuantizer::new(pixels, self.maximum_color_count, self.take_filters())
+                    .with_weighted_colors(self.weighted_colors)
+                    .with_weight_by_alpha(self.quantizer_options.weight_by_alpha);
+            return quantizer.histogram();
+        }
+
+        let quantizer = ColorCutQuantizer::new(
+            pixels,
+            self.maximum_color_count,
+            self.take_filters(),
+            self.quantizer_options,
+        )
+        .with_weighted_colors(self.weighted_colors);
+        quantizer.histogram()
+    }
+
+    /// Consume the builder and return the amount of distinct colors in the considered pixels.
+    ///
+    /// The colors are compared at their full 8-bit depth without any quantization or filtering.
+    /// The builder's region and resizing are respected, so a shrunk image may have fewer distinct
+    /// colors than the original.
+    pub fn distinct_color_count(mut self) -> usize {
+        let (pixels, _) = self.considered_pixels();
+        pixels
+            .iter()
+            .map(QuantizablePixel::rgb)
+            .chain(self.weighted_colors.iter().map(|&(rgb, _)| rgb))
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Consume the builder and return the most common exact color in the considered pixels, if
+    /// there are any.
+    ///
+    /// Like [`PaletteBuilder::distinct_color_count`], the colors are compared at their full 8-bit
+    /// depth without any quantization or filtering, and the builder's region and resizing are
+    /// respected. This is useful for detecting a solid background color, for example. If several
+    /// colors are equally common, the one with the greatest RGB value is returned.
+    pub fn modal_color(mut self) -> Option<(u8, u8, u8)> {
+        let (pixels, _) = self.considered_pixels();
+        let mut counts: HashMap<(u8, u8, u8), u64> = HashMap::new();
+        for pixel in pixels.iter() {
+            *counts.entry(pixel.rgb()).or_default() += 1;
+        }
+        for &(rgb, count) in self.weighted_colors.iter() {
+            *counts.entry(rgb).or_default() += count as u64;
+        }
+
+        counts
+            .into_iter()
+            .max_by_key(|&(rgb, count)| (count, rgb))
+            .map(|(rgb, _)| rgb)
+    }
+
+    /// Consume the builder and return the mean relative luminance of the considered pixels, in the
+    /// range 0 to 1.
+    ///
+    /// The relative luminance is as defined by WCAG (see [`Swatch::relative_luminance`]). The
+    /// builder's region and resizing are respected, but no palette is generated. If there are no
+    /// considered pixels, the average luminance is 0.
+    pub fn average_luminance(mut self) -> f32 {
+        let (pixels, _) = self.considered_pixels();
+        if pixels.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f64 = pixels
+            .iter()
+            .map(|pixel| color::relative_luminance(pixel.rgb()) as f64)
+            .sum();
+
+        (sum / pixels.len() as f64) as f32
+    }
+
+    /// Consume the builder and return a histogram of the considered pixels' hues.
+    ///
+    /// The hue circle from 0 to 360 degrees is divided into the given amount of equally wide bins,
+    /// and each bin counts the pixels whose HSL hue falls into it. Pixels with a saturation of at
+    /// most 0.1 are left out, since the hue of a near-gray color is mostly noise. The builder's
+    /// region and resizing are respected, but the image isn't quantized or filtered. If there are
+    /// no bins, the histogram is empty.
+    pub fn hue_histogram(mut self, bins: usize) -> Vec<u32> {
+        let mut histogram = vec![0; bins];
+        if bins == 0 {
+            return histogram;
+        }
+
+        let (pixels, _) = self.considered_pixels();
+        let colors = pixels
+            .iter()
+            .map(|pixel| (pixel.rgb(), 1))
+            .chain(self.weighted_colors.iter().copied());
+
+        for (rgb, count) in colors {
+            let (h, s, _) = rgb_to_hsl(rgb);
+            if s > HUE_HISTOGRAM_MIN_SATURATION {
+                let bin = ((h / 360.0 * bins as f32) as usize).min(bins - 1);
+                histogram[bin] += count;
+            }
+        }
+
+        histogram
+    }
+
+    /// Consume the builder and return whether the image is effectively monochrome, meaning the
+    /// population-weighted average HSL saturation of its quantized swatches is below the given
+    /// threshold.
+    ///
+    /// This is useful for skipping color-based logic for grayscale or sepia-toned images. The
+    /// swatches are generated as with [`PaletteBuilder::generate`], so the builder's settings are
+    /// respected. An image with no swatches is considered monochrome.
+    pub fn is_monochrome(self, saturation_threshold: f32) -> bool {
+        let palette = self.generate();
+        let (sum, total) = palette
+            .swatches()
+            .iter()
+            .fold((0.0, 0.0), |(sum, total), swatch| {
+                let (_, s, _) = swatch.hsl();
+                let population = swatch.population() as f32;
+                (sum + s * population, total + population)
+            });
+
+        total == 0.0 || sum / total < saturation_threshold
+    }
+
+    /// Consume the builder and return the image's background color, if it has a consistent one.
+    ///
+    /// The background color is detected by sampling the pixels along the edges of the image after
+    /// it has been shrunk (see [`PaletteBuilder::resize_image_area`]). If most of the edge pixels
+    /// share a similar color, their average color is returned as the background color. The set
+    /// region does not affect the detection.
+    pub fn detected_background(mut self) -> Option<(u8, u8, u8)> {
+        self.scale_image_down();
+        self.detect_background()
+    }
+
+    /// Take the filters to quantize with out of the builder, starting with the default filter if
+    /// it's used. In grayscale mode, the grayscale filter is used in place of the default filter.
+    fn take_filters(&mut self) -> Vec<StagedFilter> {
+        let mut filters = Vec::new();
+        if self.use_default_filter && self.grayscale {
+            filters.push(StagedFilter::new(Box::new(GrayscaleFilter), false, true));
+        } else if self.use_default_filter {
+            filters.push(StagedFilter::new(Box::new(DefaultFilter::default()), true, true));
+        }
+        if let Some(minimum_saturation) = self.minimum_saturation {
+            let filter = MinimumSaturationFilter { minimum_saturation };
+            filters.push(StagedFilter::new(Box::new(filter), true, true));
+        }
+
+        filters.append(&mut self.filters);
+        filters
+    }
+
+    /// Scale down the image if requested and return the pixels in the requested region, or in the
+    /// entire image, sampled with the requested stride. If requested, pixels close to the detected
+    /// background color are excluded and the remaining pixels randomly sampled. Along with the
+    /// pixels, the amount of pixels each returned pixel represents is returned.
+    fn considered_pixels(&mut self) -> (Vec<P>, f32) {
+        // scale down the image if requested, and the region to match the new scaled image
+        if let Some(scale_ratio) = self.scale_image_down() {
+            self.region = self
+                .region
+                .map(|region| scale_region(region, scale_ratio, self.image.dimensions()));
+        }
+
+        let background = if self.exclude_background {
+            self.detect_background()
+        } else {
+            None
+        };
+
+        // get pixels in the requested region, or in the entire image
+        let mut pixels: Vec<P> = if let Some(region) = self.region {
+            // iterating over an empty view doesn't stop at its bounds
+            if region.width == 0 || region.height == 0 {
+                return (Vec::new(), 1.0);
+            }
+
+            self.image
+                .view(region.x, region.y, region.width, region.height)
+                .pixels()
+                .step_by(self.sample_stride)
+                .map(|(_, _, p)| p)
                 .collect()
         } else {
-            self.image.pixels().copied().collect()
+            self.image.pixels().step_by(self.sample_stride).copied().collect()
         };
 
-        // quantize pixels, get swatches
-        let quantizer = ColorCutQuantizer::new(pixels, self.maximum_color_count, self.filters);
-        let swatches = quantizer.get_quantized_colors();
+        if self.premultiplied {
+            // fully transparent pixels have no color to recover
+            pixels.retain(|pixel| pixel.alpha() > 0);
+
+            for pixel in pixels.iter_mut() {
+                let alpha = pixel.alpha() as u32;
+                pixel.apply_without_alpha(|c| {
+                    ((c as u32 * 255 + alpha / 2) / alpha).min(255) as u8
+                });
+            }
+        }
+
+        if let Some(background) = background {
+            pixels.retain(|pixel| !is_near_color(pixel.rgb(), background));
+        }
+
+        // the populations of weighted colors given in place of an image aren't affected by sampling
+        let mut population_scale = if pixels.is_empty() {
+            1.0
+        } else {
+            self.sample_stride as f32
+        };
+        if let Some(sample_count) = self.sample_count.filter(|&count| pixels.len() > count) {
+            let mut rng = match self.sample_seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+
+            population_scale *= pixels.len() as f32 / sample_count as f32;
+            pixels = pixels.into_iter().choose_multiple(&mut rng, sample_count);
+        }
+
+        (pixels, population_scale)
+    }
+
+    fn detect_background(&self) -> Option<(u8, u8, u8)> {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        // sample every pixel along the edges of the image
+        let edges = (0..width)
+            .flat_map(|x| [(x, 0), (x, height - 1)])
+            .chain((1..height.saturating_sub(1)).flat_map(|y| [(0, y), (width - 1, y)]));
+
+        // cluster the edge pixels by their coarsely quantized color, keeping track of the
+        // population and the sum of each color channel in every cluster
+        let mut clusters: HashMap<(u8, u8, u8), (u32, [u32; 3])> = HashMap::new();
+        let mut edge_population = 0;
+        for (x, y) in edges {
+            let (r, g, b) = self.image.get_pixel(x, y).rgb();
+            let (pop, sums) = clusters.entry((r >> 4, g >> 4, b >> 4)).or_default();
+
+            *pop += 1;
+            sums[0] += r as u32;
+            sums[1] += g as u32;
+            sums[2] += b as u32;
+            edge_population += 1;
+        }
+
+        // the largest cluster is the background if it covers most of the edges
+        let (pop, sums) = clusters.into_values().max_by_key(|(pop, _)| *pop)?;
+        if (pop as f32) < edge_population as f32 * BACKGROUND_MIN_EDGE_FRACTION {
+            return None;
+        }
+
+        Some((
+            (sums[0] / pop) as u8,
+            (sums[1] / pop) as u8,
+            (sums[2] / pop) as u8,
+        ))
+    }
+
+    /// Scale down the image if requested, returning the ratio it was scaled by if it was.
+    fn scale_image_down(&mut self) -> Option<f32>
+    where
+        <P as image::Pixel>::Subpixel: 'static,
+    {
+        let (image, scale_ratio) = scale_image_to_area(&self.image, self.resize_area)?;
+        self.image = image;
+        Some(scale_ratio)
+    }
+}
+
+/// Returns a copy of an image shrunk to roughly the given area while keeping its aspect ratio,
+/// along with the ratio both of its dimensions were scaled by, or `None` if the image is already
+/// small enough or no area is given.
+fn scale_image_to_area<P>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    resize_area: Option<u32>,
+) -> Option<(ImageBuffer<P, Vec<u8>>, f32)>
+where
+    P: image::Pixel<Subpixel = u8> + 'static,
+{
+    let (width, height) = image.dimensions();
+    let area = width * height;
+
+    let scale_ratio = match resize_area {
+        Some(resize_area) if resize_area > 0 && area > resize_area => {
+            (resize_area as f32 / area as f32).sqrt()
+        }
+        _ => return None,
+    };
+
+    let image = image::imageops::resize(
+        image,
+        (width as f32 * scale_ratio).ceil() as u32,
+        (height as f32 * scale_ratio).ceil() as u32,
+        image::imageops::FilterType::Nearest,
+    );
+    Some((image, scale_ratio))
+}
+
+/// Scale a region of an image by the given ratio in both axes to cover the same area in the image
+/// resized by the ratio, which has the given new dimensions. The scaled region is kept within the
+/// resized image.
+fn scale_region(region: Rect, scale_ratio: f32, (new_width, new_height): (u32, u32)) -> Rect {
+    // the near edges are rounded down and the far edges up, so the scaled region never loses any
+    // of the area
+    let x = ((region.x as f32 * scale_ratio).floor() as u32).min(new_width);
+    let y = ((region.y as f32 * scale_ratio).floor() as u32).min(new_height);
+    let right = region.x.saturating_add(region.width) as f32 * scale_ratio;
+    let bottom = region.y.saturating_add(region.height) as f32 * scale_ratio;
+    let right = (right.ceil() as u32).min(new_width);
+    let bottom = (bottom.ceil() as u32).min(new_height);
+
+    Rect {
+        x,
+        y,
+        width: right - x,
+        height: bottom - y,
+    }
+}
+
+fn select_swatches(
+    swatches: &[Swatch],
+    targets: &mut [Target],
+    scoring: &ScoringOptions,
+) -> (BTreeMap<u64, Option<Swatch>>, BTreeSet<u64>) {
+    let mut used_colors = HashSet::new();
+    let mut fallback_targets = BTreeSet::new();
+    let selected_swatches = targets
+        .iter_mut()
+        .map(|target| {
+            target.normalize_weights();
+
+            let mut selected = generate_scored_target(swatches, *target, &mut used_colors, scoring);
+
+            // widen the target's ranges step by step until a swatch is found, if requested
+            let mut relaxed = *target;
+            for _ in 0..scoring.relax_steps {
+                if selected.is_some() {
+                    break;
+                }
+
+                relaxed = relaxed.relaxed(scoring.relax_step_size);
+                selected = generate_scored_target(swatches, relaxed, &mut used_colors, scoring);
+            }
+
+            if selected.is_none() {
+                selected = scoring.fallback.select(swatches, *target);
+
+                if selected.is_some() {
+                    fallback_targets.insert(target.id());
+                }
+            }
+
+            // tag swatches selected for the preset targets with the preset's name
+            if let Some(preset) = target.preset() {
+                selected = selected.map(|swatch| swatch.with_name(preset.name()));
+            }
+
+            (target.id(), selected)
+        })
+        .collect();
+
+    (selected_swatches, fallback_targets)
+}
+
+fn generate_scored_target(
+    swatches: &[Swatch],
+    target: Target,
+    used_colors: &mut HashSet<(u8, u8, u8)>,
+    scoring: &ScoringOptions,
+) -> Option<Swatch> {
+    if target.is_exclusive() {
+        if let Some(max_scored_swatch) =
+            get_max_scored_swatch_for_target(swatches, target, used_colors, scoring)
+        {
+            used_colors.insert(max_scored_swatch.rgb());
+            return Some(max_scored_swatch);
+        }
+    }
+
+    None
+}
+
+fn get_max_scored_swatch_for_target(
+    swatches: &[Swatch],
+    target: Target,
+    used_colors: &HashSet<(u8, u8, u8)>,
+    scoring: &ScoringOptions,
+) -> Option<Swatch> {
+    let dominant_swatch = scoring.reference_swatch(swatches, target, used_colors);
+
+    // a NaN score, such as from a custom score function, is treated as the lowest possible score
+    let score = |swatch| {
+        let score = scoring.score(swatch, dominant_swatch, target);
+        if score.is_nan() {
+            f32::NEG_INFINITY
+        } else {
+            score
+        }
+    };
+
+    swatches
+        .iter()
+        .copied()
+        .filter(|swatch| should_be_scored_for_target(*swatch, target, used_colors))
+        .max_by(|lhs, rhs| {
+            // break ties by population and then by color so the selection doesn't depend on the
+            // order of the swatches
+            score(*lhs)
+                .total_cmp(&score(*rhs))
+                .then_with(|| lhs.population().cmp(&rhs.population()))
+                .then_with(|| lhs.rgb().cmp(&rhs.rgb()))
+        })
+}
+
+/// Returns the swatch with the largest population, breaking ties by the greatest color.
+fn dominant_swatch(swatches: &[Swatch]) -> Option<Swatch> {
+    swatches
+        .iter()
+        .copied()
+        .max_by_key(|swatch| (swatch.population(), swatch.rgb()))
+}
+
+fn should_be_scored_for_target(
+    swatch: Swatch,
+    target: Target,
+    used_colors: &HashSet<(u8, u8, u8)>,
+) -> bool {
+    let (s, l) = target.space().saturation_and_lightness(swatch);
+
+    (target.minimum_saturation()..=target.maximum_saturation()).contains(&s)
+        && (target.minimum_lightness()..=target.maximum_lightness()).contains(&l)
+        && !used_colors.contains(&swatch.rgb())
+}
+
+impl ScoringOptions {
+    /// Returns the swatch whose population the swatches' populations are compared to when scoring
+    /// them for a target, according to the population reference.
+    fn reference_swatch(
+        &self,
+        swatches: &[Swatch],
+        target: Target,
+        used_colors: &HashSet<(u8, u8, u8)>,
+    ) -> Option<Swatch> {
+        match self.population_reference {
+            PopulationReference::GlobalDominant => dominant_swatch(swatches),
+            PopulationReference::InRangeDominant => swatches
+                .iter()
+                .copied()
+                .filter(|swatch| should_be_scored_for_target(*swatch, target, used_colors))
+                .max_by_key(|swatch| (swatch.population(), swatch.rgb())),
+        }
+    }
+
+    fn score(&self, swatch: Swatch, dominant_swatch: Option<Swatch>, target: Target) -> f32 {
+        match &self.score_fn {
+            Some(score_fn) => score_fn(swatch, dominant_swatch, target),
+            None => generate_score(swatch, dominant_swatch, target, self.population_scale),
+        }
+    }
+}
+
+impl TargetFallback {
+    fn select(self, swatches: &[Swatch], target: Target) -> Option<Swatch> {
+        match self {
+            TargetFallback::None => None,
+            TargetFallback::Dominant => dominant_swatch(swatches),
+            TargetFallback::Nearest => swatches.iter().copied().min_by(|lhs, rhs| {
+                distance_to_target(*lhs, target)
+                    .total_cmp(&distance_to_target(*rhs, target))
+                    .then_with(|| rhs.population().cmp(&lhs.population()))
+                    .then_with(|| lhs.rgb().cmp(&rhs.rgb()))
+            }),
+        }
+    }
+}
+
+/// Returns the distance between a swatch's saturation and lightness and the target's.
+fn distance_to_target(swatch: Swatch, target: Target) -> f32 {
+    let (saturation, lightness) = target.space().saturation_and_lightness(swatch);
+
+    (saturation - target.target_saturation()).hypot(lightness - target.target_lightness())
+}
+
+fn generate_score(
+    swatch: Swatch,
+    dominant_swatch: Option<Swatch>,
+    target: Target,
+    population_scale: PopulationScale,
+) -> f32 {
+    let (saturation, lightness) = target.space().saturation_and_lightness(swatch);
+
+    let max_population = dominant_swatch.map_or(0, |swatch| swatch.population()) as f32;
+
+    // a score with zero weight is left out entirely, so a degenerate score can't turn the total
+    // into NaN by being multiplied with zero
+    let weighted = |weight: f32, score: f32| if weight == 0.0 { 0.0 } else { weight * score };
+
+    // calculate scores for saturation and luminance based on how close to the target values they
+    // are, weighted by the target
+    let saturation_score = weighted(
+        target.saturation_weight(),
+        1.0 - (saturation - target.target_saturation()).abs(),
+    );
+    let lightness_score = weighted(
+        target.lightness_weight(),
+        1.0 - (lightness - target.target_lightness()).abs(),
+    );
+
+    // calculate score for the population based on how large it is compared to the dominant swatch,
+    // weighted by the target. without a populated dominant swatch there's nothing to compare to
+    let population_ratio = if max_population > 0.0 {
+        match population_scale {
+            PopulationScale::Linear => swatch.population() as f32 / max_population,
+            PopulationScale::Log => (swatch.population() as f32).ln_1p() / max_population.ln_1p(),
+        }
+    } else {
+        0.0
+    };
+    let population_score = weighted(target.population_weight(), population_ratio);
+
+    saturation_score + lightness_score + population_score
+}
+
+/// Merge swatches whose HSL hues and lightnesses are within the given tolerances of each other. The
+/// most populous swatches are merged into first.
+fn merge_similar_hsl(
+    mut swatches: Vec<Swatch>,
+    hue_tolerance: f32,
+    lightness_tolerance: f32,
+) -> Vec<Swatch> {
+    swatches.sort_by_key(|swatch| std::cmp::Reverse(swatch.population()));
+
+    let mut merged: Vec<Swatch> = Vec::with_capacity(swatches.len());
+    for swatch in swatches {
+        let (h, _, l) = swatch.hsl();
+        let similar = merged.iter_mut().find(|other| {
+            let (other_h, _, other_l) = other.hsl();
+            let hue_distance = (h - other_h).abs();
+
+            hue_distance.min(360.0 - hue_distance) <= hue_tolerance
+                && (l - other_l).abs() <= lightness_tolerance
+        });
+
+        match similar {
+            Some(other) => *other = merge_swatches(*other, swatch),
+            None => merged.push(swatch),
+        }
+    }
+
+    merged
+}
+
+/// Merge two swatches into one with their population-weighted average color and their combined
+/// population.
+fn merge_swatches(left: Swatch, right: Swatch) -> Swatch {
+    let population = left.population() as u64 + right.population() as u64;
+    if population == 0 {
+        return left;
+    }
+
+    let (lr, lg, lb) = left.rgb();
+    let (rr, rg, rb) = right.rgb();
+    let average = |l: u8, r: u8| {
+        let sum = l as u64 * left.population() as u64 + r as u64 * right.population() as u64;
+        ((sum + population / 2) / population) as u8
+    };
+
+    let population = population.min(u32::MAX as u64) as u32;
+    Swatch::new((average(lr, rr), average(lg, rg), average(lb, rb)), population)
+}
+
+/// Interpolate linearly between two swatches' colors and populations by the given amount. The
+/// name of the first swatch is kept, or that of the second if the first has none.
+fn blend_swatches(from: Swatch, to: Swatch, t: f32) -> Swatch {
+    let lerp = |from: f32, to: f32| from + (to - from) * t;
+    let (fr, fg, fb) = from.rgb();
+    let (tr, tg, tb) = to.rgb();
+    let channel = |from: u8, to: u8| lerp(from as f32, to as f32).round() as u8;
+
+    let population = lerp(from.population() as f32, to.population() as f32).round() as u32;
+    let swatch = Swatch::new((channel(fr, tr), channel(fg, tg), channel(fb, tb)), population);
+
+    match from.name().or(to.name()) {
+        Some(name) => swatch.with_name(name),
+        None => swatch,
+    }
+}
+
+/// Escape a string for use within a JSON string literal.
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn is_near_color((r, g, b): (u8, u8, u8), (other_r, other_g, other_b): (u8, u8, u8)) -> bool {
+    r.abs_diff(other_r) <= BACKGROUND_COLOR_TOLERANCE
+        && g.abs_diff(other_g) <= BACKGROUND_COLOR_TOLERANCE
+        && b.abs_diff(other_b) <= BACKGROUND_COLOR_TOLERANCE
+}
+
+// thank you SO. https://stackoverflow.com/a/39147465
+fn rgb_to_hsl((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let r = r as f32 / 255.0;
+    let g = g as f32 / 255.0;
+    let b = b as f32 / 255.0;
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let c = max - min;
+
+    let l = (max + min) / 2.0;
+    let (h, s) = if c == 0.0 {
+        (0.0, 0.0)
+    } else {
+        let s = c / (1.0 - (2.0 * l - 1.0).abs());
+
+        let (segment, shift) = if max == r {
+            ((g - b) / c, if (g - b) / c < 0.0 { 6.0 } else { 0.0 })
+        } else if max == g {
+            ((b - r) / c, 2.0)
+        } else {
+            ((r - g) / c, 4.0)
+        };
+
+        (segment + shift, s)
+    };
+
+    (h * 60.0, s, l)
+}
+
+/// The inverse of [`rgb_to_hsl`], converting an HSL color with the hue in degrees back into 8-bit
+/// RGB. The hue wraps around and the saturation and lightness are clamped between 0 and 1.
+fn hsl_to_rgb((h, s, l): (f32, f32, f32)) -> (u8, u8, u8) {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let s = s.clamp(0.0, 1.0);
+    let l = l.clamp(0.0, 1.0);
+
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    let m = l - c / 2.0;
+    let to_u8 = |value: f32| ((value + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (to_u8(r), to_u8(g), to_u8(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    use super::*;
+
+    /// A filter rejecting every color with a blue component of at least the given value.
+    struct BlueBelow(u8);
+    impl Filter for BlueBelow {
+        fn is_allowed(&self, (_, _, b): (u8, u8, u8), _: (f32, f32, f32)) -> bool {
+            b < self.0
+        }
+    }
+
+    /// A gradient from black to blue, with a distinct quantized color every 8 pixels.
+    fn blue_gradient() -> RgbImage {
+        RgbImage::from_fn(256, 4, |x, _| Rgb([0, 0, x as u8]))
+    }
+
+    /// A filter rejecting every color with a lightness below the given value.
+    struct LighterThan(f32);
+    impl Filter for LighterThan {
+        fn is_allowed(&self, _: (u8, u8, u8), (_, _, l): (f32, f32, f32)) -> bool {
+            l >= self.0
+        }
+    }
+
+    /// A gradient from black to white, with every lightness value in its own column.
+    fn gray_gradient() -> GrayImage {
+        GrayImage::from_fn(256, 4, |x, _| Luma([x as u8]))
+    }
+
+    #[test]
+    fn grayscale_mode_keeps_custom_filters() {
+        let palette = PaletteBuilder::from_image(gray_gradient())
+            .maximum_color_count(4)
+            .add_filter(LighterThan(0.5))
+            .generate();
+
+        assert!(!palette.is_empty());
+        assert!(palette.swatches().iter().all(|swatch| swatch.rgb().0 >= 128));
+    }
+
+    #[test]
+    fn grayscale_mode_respects_disabling_the_default_filter() {
+        // every pixel is in the darkest bucket, whose mean the grayscale filter rejects as black
+        let gradient = GrayImage::from_fn(256, 4, |x, _| Luma([x as u8 / 64]));
+        let filtered = PaletteBuilder::from_image(gradient.clone()).generate();
+        let unfiltered = PaletteBuilder::from_image(gradient)
+            .use_default_filter(false)
+            .generate();
+
+        assert!(filtered.is_empty());
+        assert!(!unfiltered.is_empty());
+    }
+
+    #[test]
+    fn grayscale_histogram_is_of_lightness() {
+        let histogram = PaletteBuilder::from_image(gray_gradient())
+            .add_filter(LighterThan(0.5))
+            .histogram();
+
+        assert_eq!(histogram.len(), 128);
+        assert!(histogram
+            .iter()
+            .all(|&((r, g, b), count)| r == g && g == b && r >= 128 && count == 4));
+    }
+
+    #[test]
+    fn heavily_weighted_colors_do_not_overflow() {
+        let colors: Vec<_> = (0..40).map(|i| ((i * 6, 100, 50), u32::MAX / 8)).collect();
+
+        for (representative, linear_averaging, refine) in [
+            (Representative::Mean, false, 0),
+            (Representative::Mean, true, 0),
+            (Representative::Median, false, 0),
+            (Representative::Mean, false, 2),
+        ] {
+            let palette = PaletteBuilder::from_weighted_colors(colors.clone())
+                .maximum_color_count(2)
+                .clear_filters()
+                .representative_color(representative)
+                .linear_averaging(linear_averaging)
+                .refine_swatches(refine)
+                .minimum_population_fraction(0.1)
+                .generate();
+
+            assert_eq!(palette.len(), 2);
+            for swatch in palette.swatches() {
+                assert_eq!(swatch.population(), u32::MAX);
+
+                let (r, g, b) = swatch.rgb();
+                assert!(r < 240 && (96..=104).contains(&g) && (46..=54).contains(&b));
+            }
+        }
+    }
+
+    #[test]
+    fn builders_with_filters_are_send() {
+        let builder = PaletteBuilder::from_image(blue_gradient())
+            .add_filter(BlueBelow(128))
+            .add_filters([Box::new(BlueBelow(64)) as BoxedFilter]);
+        let palette = std::thread::spawn(move || builder.generate()).join().unwrap();
+
+        assert!(palette.swatches().iter().all(|swatch| swatch.rgb().2 < 64));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn generate_async_matches_generate() {
+        let builder = || {
+            PaletteBuilder::from_image(blue_gradient())
+                .clear_filters()
+                .add_filter(BlueBelow(128))
+                .deterministic(true)
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let palette = runtime.block_on(builder().generate_async());
+
+        assert_eq!(palette.swatches(), builder().generate().swatches());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn generate_in_pool_matches_generate() {
+        // enough distinct colors and requested colors for the boxes to be split in parallel
+        let image = RgbImage::from_fn(128, 128, |x, y| {
+            Rgb([x as u8 * 2, y as u8 * 2, ((x * 7 + y * 13) % 256) as u8])
+        });
+        let builder = || {
+            PaletteBuilder::from_image(image.clone())
+                .resize_image_area(None)
+                .maximum_color_count(64)
+                .deterministic(true)
+        };
+
+        let (palette, stats) = builder().generate_with_stats();
+        assert!(stats.histogram_colors >= 4096);
+        assert_eq!(stats.quantized_colors, 64);
+
+        for threads in [1, 2] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let pooled = builder().generate_in_pool(&pool);
+
+            assert_eq!(pooled.swatches(), palette.swatches());
+        }
+    }
+
+    #[test]
+    fn filters_rejecting_every_color_leave_no_swatches() {
+        let (palette, stats) = PaletteBuilder::from_image(blue_gradient())
+            .maximum_color_count(4)
+            .clear_filters()
+            .add_filter(BlueBelow(0))
+            .generate_with_stats();
+
+        assert!(palette.is_empty());
+        assert_eq!(stats.histogram_colors, 32);
+        assert_eq!(stats.quantized_colors, 0);
+    }
+
+    #[test]
+    fn filtered_colors_decide_whether_to_quantize() {
+        // only 4 quantized colors are left after filtering, so each is its own swatch
+        let palette = PaletteBuilder::from_image(blue_gradient())
+            .maximum_color_count(8)
+            .clear_filters()
+            .add_filter(BlueBelow(32))
+            .generate();
+
+        assert_eq!(palette.len(), 4);
+        assert!(palette.swatches().iter().all(|swatch| swatch.population() == 32));
+    }
+
+    #[test]
+    fn histogram_counts_the_allowed_pixels() {
+        let histogram = PaletteBuilder::from_image(blue_gradient())
+            .clear_filters()
+            .add_filter(BlueBelow(128))
+            .histogram();
+
+        let total: u32 = histogram.iter().map(|&(_, count)| count).sum();
+        assert_eq!(total, 128 * 4);
+        assert!(histogram.iter().all(|&((_, _, b), _)| b < 128));
+    }
+
+    #[test]
+    fn grayscale_gradient_yields_distinct_grays() {
+        let palette = PaletteBuilder::from_image(gray_gradient()).generate();
+        let grays: HashSet<_> = palette.swatches().iter().map(|swatch| swatch.rgb()).collect();
+
+        assert!(grays.len() >= 4);
+        assert!(grays.iter().all(|&(r, g, b)| r == g && g == b));
+    }
+
+    /// An image of vertical stripes of the given colors, each the given amount of pixels wide.
+    fn stripes(stripes: &[((u8, u8, u8), u32)]) -> RgbImage {
+        let columns: Vec<_> = stripes
+            .iter()
+            .flat_map(|&((r, g, b), width)| std::iter::repeat_n(Rgb([r, g, b]), width as usize))
+            .collect();
+
+        RgbImage::from_fn(columns.len() as u32, 4, |x, _| columns[x as usize])
+    }
+
+    #[test]
+    fn distinct_colors_are_counted_at_full_depth() {
+        // the first two colors quantize into the same color
+        let image = stripes(&[((10, 20, 30), 10), ((11, 20, 30), 10), ((255, 255, 255), 10)]);
+
+        assert_eq!(PaletteBuilder::from_image(image).distinct_color_count(), 3);
+    }
+
+    #[test]
+    fn dominant_hue_follows_the_most_common_color() {
+        let hue_distance = |hue: f32, expected: f32| {
+            let distance = (hue - expected).abs();
+            distance.min(360.0 - distance)
+        };
+
+        let red = PaletteBuilder::from_image(stripes(&[((230, 20, 20), 80), ((128, 128, 128), 20)]))
+            .generate();
+        let cyan = PaletteBuilder::from_image(stripes(&[((20, 230, 230), 80), ((230, 20, 20), 5)]))
+            .generate();
+
+        assert!(hue_distance(red.dominant_hue().unwrap(), 0.0) < 5.0);
+        assert!(hue_distance(cyan.dominant_hue().unwrap(), 180.0) < 10.0);
+    }
+
+    #[test]
+    fn oklab_quantization_separates_pastels_better() {
+        // pastel colors of every hue, getting lighter towards the bottom
+        let image = RgbImage::from_fn(112, 112, |x, y| {
+            let hue = x as f32 / 112.0 * 360.0;
+            let (r, g, b) = hsl_to_rgb((hue, 0.6, 0.75 + y as f32 / 112.0 * 0.15));
+            Rgb([r, g, b])
+        });
+
+        // the smallest perceptual distance between any two swatches
+        let separation = |kind| {
+            let palette = PaletteBuilder::from_image(image.clone())
+                .maximum_color_count(8)
+                .clear_filters()
+                .quantizer_kind(kind)
+                .generate();
+            assert_eq!(palette.len(), 8);
+
+            let colors: Vec<_> =
+                palette.swatches().iter().map(|swatch| color::rgb_to_oklab(swatch.rgb())).collect();
+            colors
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &lhs)| colors[i + 1..].iter().map(move |&rhs| (lhs, rhs)))
+                .map(|(lhs, rhs)| color::lab_distance(lhs, rhs))
+                .fold(f32::INFINITY, f32::min)
+        };
+
+        assert!(separation(QuantizerKind::OkLab) > separation(QuantizerKind::Rgb));
+    }
+
+    #[test]
+    fn oklch_target_uses_perceptual_lightness() {
+        // both colors have the same HSL lightness, but yellow is perceptually much lighter. they
+        // are exact quantized colors, so they become swatches as is
+        let (yellow, blue) = ((248, 248, 0), (0, 0, 248));
+        let image = stripes(&[(yellow, 60), (blue, 20)]);
+
+        let hsl = Target::around_hsl(0.0, 1.0, 0.5, 0.1);
+        let oklch = Target::oklch((0.2, 0.3, 0.4), (0.4, 0.5, 0.6));
+        let palette = PaletteBuilder::from_image(image)
+            .clear_filters()
+            .clear_targets()
+            .add_targets([hsl, oklch])
+            .generate();
+
+        assert_eq!(palette.get_swatch_for_target(hsl).map(Swatch::rgb), Some(yellow));
+        assert_eq!(palette.get_swatch_for_target(oklch).map(Swatch::rgb), Some(blue));
+    }
+
+    #[test]
+    fn minimum_population_fraction_drops_rare_colors() {
+        // the blue stripe covers 0.5% of the image
+        let (red, blue) = ((200, 40, 40), (40, 40, 200));
+        let image = stripes(&[(red, 199), (blue, 1)]);
+        let colors = |fraction| -> Vec<_> {
+            PaletteBuilder::from_image(image.clone())
+                .minimum_population_fraction(fraction)
+                .generate()
+                .swatches()
+                .iter()
+                .map(|swatch| swatch.rgb())
+                .collect()
+        };
+
+        assert!(colors(0.0).contains(&blue));
+        assert_eq!(colors(0.01), [red]);
+    }
+
+    #[test]
+    fn constant_score_fn_selects_by_the_tie_break() {
+        // every swatch in range scores the same, so the most populous one is selected. the gray is
+        // the most populous overall but isn't in the vibrant target's range
+        let image = stripes(&[((200, 40, 40), 30), ((40, 40, 200), 50), ((128, 128, 128), 100)]);
+        let palette = PaletteBuilder::from_image(image)
+            .clear_filters()
+            .clear_targets()
+            .add_target(Target::vibrant())
+            .score_fn(|_, _, _| 1.0)
+            .generate();
+
+        assert_eq!(palette.vibrant_color(), Some((40, 40, 200)));
+    }
+
+    #[test]
+    fn log_population_scale_favors_small_vivid_swatches() {
+        // a large, barely vibrant swatch and a small, very vibrant one
+        let (dull, vivid) = ((176, 80, 80), (8, 8, 248));
+        let image = stripes(&[(dull, 1000), (vivid, 100)]);
+        let vibrant = |scale| {
+            PaletteBuilder::from_image(image.clone())
+                .clear_targets()
+                .add_target(Target::vibrant())
+                .population_scale(scale)
+                .generate()
+                .vibrant_color()
+        };
+
+        assert_eq!(vibrant(PopulationScale::Linear), Some(dull));
+        assert_eq!(vibrant(PopulationScale::Log), Some(vivid));
+    }
+
+    #[test]
+    fn uniform_border_is_detected_and_excluded() {
+        // a white that's an exact quantized color, so it becomes a swatch as is
+        let white = (248, 248, 248);
+        let image = RgbImage::from_fn(40, 40, |x, y| match (x, y) {
+            (5..=34, 5..=19) => Rgb([200, 40, 40]),
+            (5..=34, 20..=34) => Rgb([40, 40, 200]),
+            _ => Rgb([248, 248, 248]),
+        });
+        let colors = |exclude_background| -> Vec<_> {
+            PaletteBuilder::from_image(image.clone())
+                .clear_filters()
+                .exclude_background(exclude_background)
+                .generate()
+                .swatches()
+                .iter()
+                .map(|swatch| swatch.rgb())
+                .collect()
+        };
+
+        assert_eq!(PaletteBuilder::from_image(image.clone()).detected_background(), Some(white));
+        assert!(colors(false).contains(&white));
+        assert!(!colors(true).contains(&white));
+        assert_eq!(colors(true).len(), 2);
+    }
+
+    #[test]
+    fn palette_length_counts_the_swatches() {
+        let empty = PaletteBuilder::from_image(RgbImage::new(0, 0)).generate();
+        let palette = PaletteBuilder::from_image(blue_gradient())
+            .maximum_color_count(4)
+            .generate();
+
+        assert!(empty.is_empty());
+        assert_eq!(empty.len(), 0);
+        assert!(!palette.is_empty());
+        assert_eq!(palette.len(), palette.swatches().len());
+    }
+
+    /// A grid of colors of every hue from left to right, alternating between vivid and muted rows
+    /// and getting lighter towards the bottom.
+    fn hue_grid() -> RgbImage {
+        RgbImage::from_fn(64, 64, |x, y| {
+            let saturation = if y % 2 == 0 { 0.9 } else { 0.25 };
+            let (r, g, b) = hsl_to_rgb((x as f32 * 5.625, saturation, 0.15 + y as f32 / 90.0));
+            Rgb([r, g, b])
+        })
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn palettes_round_trip_through_ron() {
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+        assert!(palette.target_selections().filter(|(_, swatch)| swatch.is_some()).count() >= 4);
+
+        let serialized = ron::to_string(&palette).unwrap();
+        let mut restored: Palette = ron::from_str(&serialized).unwrap();
+
+        // the selections may be stale, so get rid of them entirely
+        restored.selected_swatches.clear();
+        restored.recompute_selected();
+
+        assert_eq!(restored, palette);
+        for (target, swatch) in palette.target_selections() {
+            assert_eq!(restored.get_swatch_for_target(*target), swatch);
+        }
+    }
+
+    #[test]
+    fn cloned_palettes_have_the_same_swatches() {
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+        let cloned = palette.clone();
+
+        assert_eq!(cloned.swatches(), palette.swatches());
+        assert!(cloned.target_selections().eq(palette.target_selections()));
+    }
+
+    #[test]
+    fn palettes_from_the_same_image_and_settings_are_equal() {
+        let palette = |maximum_color_count| {
+            PaletteBuilder::from_image(hue_grid())
+                .maximum_color_count(maximum_color_count)
+                .generate()
+        };
+
+        assert_eq!(palette(16), palette(16));
+        assert_ne!(palette(16), palette(8));
+    }
+
+    #[test]
+    fn add_targets_adds_every_new_target() {
+        let targets = [Target::new(), Target::new(), Target::new()];
+        let builder = PaletteBuilder::from_image(blue_gradient())
+            .add_targets(targets)
+            .add_targets([targets[0], Target::vibrant()]);
+
+        assert_eq!(builder.targets.len(), 9);
+        assert_eq!(builder.targets[6..], targets);
+    }
+
+    #[test]
+    fn removed_presets_are_not_selected() {
+        let palette = PaletteBuilder::from_image(hue_grid())
+            .remove_preset(PresetTarget::Vibrant)
+            .generate();
+
+        assert_eq!(palette.targets().len(), 5);
+        assert_eq!(palette.vibrant_swatch(), None);
+        assert!(palette.dark_vibrant_swatch().is_some());
+    }
+
+    #[test]
+    fn has_swatch_for_target_reports_matches() {
+        // a dark red has no light swatches to select
+        let palette = PaletteBuilder::from_image(stripes(&[((120, 16, 16), 10)])).generate();
+
+        assert!(palette.has_swatch_for_target(Target::dark_vibrant()));
+        assert!(!palette.has_swatch_for_target(Target::light_vibrant()));
+        assert!(!palette.has_swatch_for_target(Target::new()));
+    }
+
+    #[test]
+    fn fallback_selects_a_swatch_for_unmatched_targets() {
+        // muted colors only, none of which are within the vibrant target's ranges
+        let image = stripes(&[((96, 104, 120), 30), ((136, 144, 160), 10)]);
+        let palette = |fallback| {
+            PaletteBuilder::from_image(image.clone())
+                .target_fallback(fallback)
+                .generate()
+        };
+
+        assert_eq!(palette(TargetFallback::None).vibrant_swatch(), None);
+
+        let palette = palette(TargetFallback::Dominant);
+        assert_eq!(palette.vibrant_color(), Some((96, 104, 120)));
+        assert!(palette.is_fallback(Target::vibrant()));
+        assert!(!palette.is_fallback(Target::muted()));
+    }
+
+    #[test]
+    fn relaxed_targets_match_washed_out_colors() {
+        // a washed-out blue with a saturation of roughly 0.25, below the vibrant minimum of 0.35
+        let image = stripes(&[((96, 128, 160), 10)]);
+        let vibrant = |steps| {
+            PaletteBuilder::from_image(image.clone())
+                .clear_targets()
+                .add_target(Target::vibrant())
+                .relax_unmatched_targets(steps, 0.05)
+                .generate()
+                .vibrant_color()
+        };
+
+        assert_eq!(vibrant(0), None);
+        assert_eq!(vibrant(1), None);
+        assert_eq!(vibrant(3), Some((96, 128, 160)));
+    }
+
+    #[test]
+    fn refinement_reduces_the_quantization_error() {
+        let image = hue_grid();
+        let error = |iterations| {
+            PaletteBuilder::from_image(image.clone())
+                .maximum_color_count(8)
+                .refine_swatches(iterations)
+                .generate()
+                .quantization_error(&image)
+        };
+
+        assert!(error(1) < error(0));
+        assert!(error(3) <= error(1));
+    }
 
-        // try to pick swatches for each target
-        let mut used_colors = HashSet::new();
-        let selected_swatches = self
-            .targets
-            .iter_mut()
-            .map(|target| {
-                target.normalize_weights();
-                (
-                    target.id(),
-                    generate_scored_target(&swatches, *target, &mut used_colors),
-                )
+    #[test]
+    fn quantization_error_decreases_with_more_colors() {
+        let image = hue_grid();
+        let errors: Vec<_> = [1, 2, 4, 8, 16, 32]
+            .into_iter()
+            .map(|maximum_color_count| {
+                PaletteBuilder::from_image(image.clone())
+                    .maximum_color_count(maximum_color_count)
+                    .clear_filters()
+                    .generate()
+                    .quantization_error(&image)
             })
             .collect();
 
-        Palette {
-            swatches,
-            targets: self.targets,
-            selected_swatches,
-        }
+        assert!(errors.windows(2).all(|pair| pair[1] < pair[0]), "{errors:?}");
+    }
+
+    #[test]
+    fn premultiplied_colors_are_recovered() {
+        use image::{Rgba, RgbaImage};
+
+        let (r, g, b) = (40, 96, 200);
+        let premultiply = |c: u8| (c as u32 * 128 / 255) as u8;
+        let pixel = Rgba([premultiply(r), premultiply(g), premultiply(b), 128]);
+        let image = RgbaImage::from_pixel(16, 16, pixel);
+        let color = |premultiplied| {
+            PaletteBuilder::from_image(image.clone())
+                .premultiplied(premultiplied)
+                .generate()
+                .most_prominent_color()
+                .unwrap()
+        };
+
+        // the recovered color is only off by the quantization
+        let (recovered_r, recovered_g, recovered_b) = color(true);
+        assert!(recovered_r.abs_diff(r) <= 8);
+        assert!(recovered_g.abs_diff(g) <= 8);
+        assert!(recovered_b.abs_diff(b) <= 8);
+        assert!(color(false).2 < 110);
+    }
+
+    #[test]
+    fn similar_blues_are_merged() {
+        let image = stripes(&[((40, 40, 200), 30), ((40, 48, 200), 10), ((200, 40, 40), 20)]);
+        let swatches = |merge: bool| {
+            let builder = PaletteBuilder::from_image(image.clone());
+            let builder = if merge { builder.merge_similar_hsl(10.0, 0.05) } else { builder };
+            builder.generate().swatches().to_vec()
+        };
+
+        assert_eq!(swatches(false).len(), 3);
+
+        let merged = swatches(true);
+        assert_eq!(merged.len(), 2);
+        assert!(merged.contains(&Swatch::new((40, 42, 200), 160)));
+    }
+
+    #[test]
+    fn deterministic_histograms_give_identical_swatches() {
+        let swatches = || {
+            PaletteBuilder::from_image(hue_grid())
+                .deterministic(true)
+                .generate()
+                .swatches()
+                .to_vec()
+        };
+
+        let first = swatches();
+        assert!(first.len() > 1);
+        assert_eq!(first, swatches());
     }
 
-    fn scale_image_down(&mut self) -> bool
+    /// Returns the colors of a palette generated with the given builder, sorted.
+    fn sorted_colors<P>(builder: PaletteBuilder<P>) -> Vec<(u8, u8, u8)>
     where
-        <P as image::Pixel>::Subpixel: 'static,
+        P: image::Pixel<Subpixel = u8> + 'static + std::cmp::Eq + std::hash::Hash,
     {
-        let (width, height) = self.image.dimensions();
-        let area = width * height;
+        let palette = builder.generate();
+        let mut colors: Vec<_> = palette.swatches().iter().map(|swatch| swatch.rgb()).collect();
+        colors.sort_unstable();
+        colors
+    }
+
+    #[test]
+    fn luma_weighting_splits_greens_before_blues() {
+        // a wide range of blues and a narrower range of greens
+        let image = RgbImage::from_fn(64, 64, |x, y| Rgb([40, y as u8 * 2, x as u8 * 4]));
+        let colors = |weighting| {
+            sorted_colors(
+                PaletteBuilder::from_image(image.clone())
+                    .maximum_color_count(8)
+                    .clear_filters()
+                    .volume_weighting(weighting),
+            )
+        };
+
+        let (uniform, luma) = (colors(VolumeWeighting::Uniform), colors(VolumeWeighting::Luma));
+        let distinct = |colors: &[(u8, u8, u8)], channel: fn(&(u8, u8, u8)) -> u8| {
+            colors.iter().map(channel).collect::<HashSet<_>>().len()
+        };
 
-        let scale_ratio = match self.resize_area {
-            Some(resize_area) if resize_area > 0 && area > resize_area => {
-                (resize_area as f32 / area as f32).sqrt()
+        assert_ne!(uniform, luma);
+        assert!(distinct(&luma, |&(_, g, _)| g) > distinct(&uniform, |&(_, g, _)| g));
+    }
+
+    #[test]
+    fn split_priorities_give_different_swatches() {
+        // a sparse gradient of every hue next to a large patch of similar greens
+        let grid = hue_grid();
+        let image = RgbImage::from_fn(128, 64, |x, y| {
+            if x < 64 {
+                *grid.get_pixel(x, y)
+            } else {
+                Rgb([40 + (x % 4) as u8 * 8, 160 + (y % 4) as u8 * 8, 40])
             }
-            _ => 0.0,
+        });
+        let colors = |priority| {
+            sorted_colors(
+                PaletteBuilder::from_image(image.clone())
+                    .maximum_color_count(8)
+                    .clear_filters()
+                    .split_priority(priority),
+            )
         };
 
-        if scale_ratio > 0.0 {
-            self.image = image::imageops::resize(
-                &self.image,
-                (width as f32 * scale_ratio).ceil() as u32,
-                (height as f32 * scale_ratio).ceil() as u32,
-                image::imageops::FilterType::Nearest,
-            );
+        assert_ne!(
+            colors(SplitPriority::Volume),
+            colors(SplitPriority::VolumeTimesPopulation)
+        );
+    }
 
-            true
-        } else {
-            false
+    #[test]
+    fn split_methods_give_different_swatches() {
+        // most of the population is in the darkest blues
+        let image = RgbImage::from_fn(256, 16, |x, _| Rgb([0, 0, (x * x / 256) as u8]));
+        let colors = |method| {
+            sorted_colors(
+                PaletteBuilder::from_image(image.clone())
+                    .maximum_color_count(4)
+                    .clear_filters()
+                    .split_method(method),
+            )
+        };
+
+        assert_ne!(
+            colors(SplitMethod::PopulationMedian),
+            colors(SplitMethod::DimensionMidpoint)
+        );
+    }
+
+    #[test]
+    fn sample_stride_quarters_the_considered_pixels() {
+        let image = stripes(&[((200, 40, 40), 300), ((40, 40, 200), 100)]);
+        let considered = |stride| -> u32 {
+            PaletteBuilder::from_image(image.clone())
+                .clear_filters()
+                .sample_stride(stride)
+                .histogram()
+                .iter()
+                .map(|&(_, count)| count)
+                .sum()
+        };
+        let dominant = |stride| {
+            PaletteBuilder::from_image(image.clone())
+                .sample_stride(stride)
+                .generate()
+                .most_prominent_color()
+        };
+
+        assert_eq!(considered(1), 1600);
+        assert_eq!(considered(4), 400);
+        assert_eq!(dominant(4), dominant(1));
+    }
+
+    #[test]
+    fn sampling_keeps_both_colors_of_a_two_color_image() {
+        let (red, blue) = ((200, 40, 40), (40, 40, 200));
+        let image = RgbImage::from_fn(200, 200, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgb([200, 40, 40])
+            } else {
+                Rgb([40, 40, 200])
+            }
+        });
+
+        let palette = PaletteBuilder::from_image(image)
+            .resize_image_area(None)
+            .sample_count(Some(1000))
+            .sample_seed(Some(865))
+            .generate();
+        let colors: HashSet<_> = palette.swatches().iter().map(|swatch| swatch.rgb()).collect();
+        let population: u32 = palette.swatches().iter().map(|swatch| swatch.population()).sum();
+
+        assert_eq!(colors, HashSet::from([red, blue]));
+        assert!(population.abs_diff(40_000) <= 2);
+    }
+
+    #[test]
+    fn timings_add_up_to_the_total() {
+        let start = Instant::now();
+        let (_, timings) = PaletteBuilder::from_image(hue_grid()).generate_with_timings();
+        let elapsed = start.elapsed();
+
+        assert_eq!(
+            timings.total(),
+            timings.resize + timings.histogram + timings.split + timings.scoring
+        );
+        assert!(timings.total() <= elapsed);
+    }
+
+    #[test]
+    fn zero_population_weight_scores_by_color_only() {
+        // the dark blue is far more common, but the red is closer to the vibrant target's lightness
+        let image = stripes(&[((32, 32, 112), 300), ((200, 40, 40), 10)]);
+        let vibrant = |target: Target| {
+            PaletteBuilder::from_image(image.clone())
+                .clear_targets()
+                .add_target(target)
+                .generate()
+                .get_swatch_for_target(target)
+                .map(Swatch::rgb)
+        };
+
+        let target = Target::new().with_weights(0.0, 1.0, 0.0);
+        assert_eq!(vibrant(target), Some((200, 40, 40)));
+        assert_eq!(vibrant(target.with_weights(0.0, 1.0, 10.0)), Some((32, 32, 112)));
+    }
+
+    #[test]
+    fn single_swatches_score_finitely() {
+        let mut target = Target::vibrant();
+        target.normalize_weights();
+        let swatch = Swatch::new((200, 40, 40), 10);
+
+        for dominant in [Some(swatch), Some(Swatch::new((0, 0, 0), 0)), None] {
+            let score = generate_score(swatch, dominant, target, PopulationScale::Log);
+            assert!(score.is_finite());
         }
+
+        let palette = PaletteBuilder::from_image(stripes(&[((200, 40, 40), 1)])).generate();
+        assert_eq!(palette.vibrant_color(), Some((200, 40, 40)));
     }
-}
 
-fn generate_scored_target(
-    swatches: &[Swatch],
-    target: Target,
-    used_colors: &mut HashSet<(u8, u8, u8)>,
-) -> Option<Swatch> {
-    if target.is_exclusive() {
-        if let Some(max_scored_swatch) =
-            get_max_scored_swatch_for_target(swatches, target, used_colors)
-        {
-            used_colors.insert(max_scored_swatch.rgb());
-            return Some(max_scored_swatch);
+    #[test]
+    fn css_gradient_starts_with_the_dominant_swatch() {
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+        let dominant = palette.most_prominent_color().map(|rgb| Swatch::new(rgb, 0).hex());
+        let gradient = palette.to_css_gradient(90.0);
+
+        assert!(gradient.starts_with("linear-gradient(90deg, "));
+        assert!(gradient.contains(&format!("{} 0%", dominant.unwrap())));
+        assert!(gradient.ends_with(" 100%)"));
+
+        let empty = PaletteBuilder::from_image(RgbImage::new(0, 0)).generate();
+        assert_eq!(
+            empty.to_css_gradient(45.0),
+            "linear-gradient(45deg, transparent 0%, transparent 100%)"
+        );
+    }
+
+    #[test]
+    fn average_luminance_of_white_and_black() {
+        let luminance = |value| {
+            PaletteBuilder::from_image(RgbImage::from_pixel(8, 8, Rgb([value; 3])))
+                .average_luminance()
+        };
+
+        assert!((luminance(255) - 1.0).abs() < 1e-4);
+        assert!(luminance(0).abs() < 1e-4);
+        assert_eq!(PaletteBuilder::from_image(RgbImage::new(0, 0)).average_luminance(), 0.0);
+    }
+
+    #[test]
+    fn grayscale_images_are_monochrome() {
+        assert!(PaletteBuilder::from_image(gray_gradient()).is_monochrome(0.1));
+        assert!(!PaletteBuilder::from_image(hue_grid()).is_monochrome(0.1));
+    }
+
+    #[test]
+    fn weighted_colors_are_quantized() {
+        // 500 reds and 500 blues of varying lightness, the reds twice as common
+        let colors: Vec<_> = (0..1000)
+            .map(|i| {
+                let value = 60 + (i % 500) as u8 / 4;
+                if i < 500 {
+                    ((value + 60, 20, 20), 2)
+                } else {
+                    ((20, 20, value + 60), 1)
+                }
+            })
+            .collect();
+
+        let palette = PaletteBuilder::from_weighted_colors(colors).generate();
+        let population = |is_red: fn(&&Swatch) -> bool| -> u32 {
+            palette.swatches().iter().filter(is_red).map(|swatch| swatch.population()).sum()
+        };
+
+        assert_eq!(palette.len(), DEFAULT_CALCULATE_NUMBER_COLORS);
+        assert_eq!(population(|swatch| swatch.rgb().0 > swatch.rgb().2), 1000);
+        assert_eq!(population(|swatch| swatch.rgb().0 < swatch.rgb().2), 500);
+        assert!(palette.vibrant_swatch().is_some());
+    }
+
+    #[test]
+    fn rescoring_with_a_single_target() {
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+        let target = Target::new();
+        let rescored = palette.rescore(vec![target]);
+
+        assert_eq!(rescored.targets(), [target]);
+        assert_eq!(rescored.swatches(), palette.swatches());
+
+        let selected: Vec<_> = rescored.target_selections().collect();
+        assert_eq!(selected.len(), 1);
+        assert!(selected[0].1.is_some());
+        assert_eq!(rescored.vibrant_swatch(), None);
+    }
+
+    #[test]
+    fn low_color_images_terminate_early() {
+        let image = stripes(&[((200, 40, 40), 10), ((40, 200, 40), 10), ((40, 40, 200), 10)]);
+        let (palette, stats) = PaletteBuilder::from_image(image).generate_with_stats();
+
+        assert_eq!(palette.len(), 3);
+        assert_eq!(
+            stats,
+            QuantizationStats {
+                requested_colors: 16,
+                histogram_colors: 3,
+                quantized_colors: 3,
+                terminated_early: true,
+            }
+        );
+
+        let (_, stats) = PaletteBuilder::from_image(hue_grid()).generate_with_stats();
+        assert_eq!(stats.quantized_colors, 16);
+        assert!(!stats.terminated_early);
+    }
+
+    #[test]
+    fn colorblind_safe_set_keeps_one_of_confusable_swatches() {
+        // the red and the green look nearly the same with deuteranopia
+        let (red, green, blue) = ((200, 40, 40), (40, 160, 40), (40, 40, 200));
+        let palette = PaletteBuilder::from_image(stripes(&[(red, 30), (green, 20), (blue, 10)]))
+            .generate();
+        let colors = |kind| -> Vec<_> {
+            palette
+                .colorblind_safe_set(kind, 20.0)
+                .iter()
+                .map(|swatch| swatch.rgb())
+                .collect()
+        };
+
+        assert_eq!(colors(ColorBlindness::Deuteranopia), [red, blue]);
+        assert_eq!(colors(ColorBlindness::Tritanopia), [red, green, blue]);
+    }
+
+    #[test]
+    fn design_tokens_are_json() {
+        type Tokens = BTreeMap<String, BTreeMap<String, BTreeMap<String, String>>>;
+
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+        let vibrant = palette.vibrant_swatch().unwrap();
+
+        // JSON objects of strings are valid RON as well
+        let tokens: Tokens = ron::from_str(&palette.to_design_tokens("color")).unwrap();
+        let vibrant_token = &tokens["color"]["vibrant"];
+
+        assert_eq!(vibrant_token["value"], vibrant.hex());
+        assert_eq!(vibrant_token["type"], "color");
+        assert_eq!(tokens["color"].len(), palette.named_colors().len());
+
+        let escaped = PaletteBuilder::from_image(RgbImage::new(0, 0))
+            .generate()
+            .to_design_tokens("a \"b\"");
+        assert_eq!(escaped, r#"{"a \"b\"":{}}"#);
+    }
+
+    #[test]
+    fn alpha_weighting_counts_opaque_coverage() {
+        use image::{Rgba, RgbaImage};
+
+        // an opaque red half and a blue half at a fifth of the opacity
+        let image = RgbaImage::from_fn(32, 16, |x, _| {
+            if x < 16 {
+                Rgba([200, 40, 40, 255])
+            } else {
+                Rgba([40, 40, 200, 51])
+            }
+        });
+        let populations = |weight_by_alpha| -> Vec<_> {
+            let mut swatches = PaletteBuilder::from_image(image.clone())
+                .weight_by_alpha(weight_by_alpha)
+                .generate()
+                .swatches()
+                .to_vec();
+            swatches.sort_by_key(|swatch| swatch.rgb());
+            swatches.iter().map(|swatch| swatch.population()).collect()
+        };
+
+        // sorted by color, so blue comes first
+        assert_eq!(populations(false), [256, 256]);
+        assert_eq!(populations(true), [51, 256]);
+    }
+
+    #[test]
+    fn disabling_the_default_filter_keeps_custom_filters() {
+        let (near_black, blue) = ((8, 8, 8), (40, 40, 200));
+        let image = stripes(&[(near_black, 10), ((200, 40, 40), 10), (blue, 10)]);
+        let colors = |use_default_filter| {
+            sorted_colors(
+                PaletteBuilder::from_image(image.clone())
+                    .add_filter(BlueBelow(128))
+                    .use_default_filter(use_default_filter),
+            )
+        };
+
+        assert_eq!(colors(true), [(200, 40, 40)]);
+        assert_eq!(colors(false), [near_black, (200, 40, 40)]);
+    }
+
+    #[test]
+    fn nan_scores_are_the_lowest() {
+        let image = stripes(&[((200, 40, 40), 30), ((40, 40, 200), 10)]);
+        let vibrant = |score_fn: fn(Swatch, Option<Swatch>, Target) -> f32| {
+            PaletteBuilder::from_image(image.clone())
+                .clear_targets()
+                .add_target(Target::vibrant())
+                .score_fn(score_fn)
+                .generate()
+                .vibrant_color()
+        };
+
+        // a NaN score loses to any other score, and a selection is made even if every score is NaN
+        let nan_for_red = |swatch: Swatch, _, _| if swatch.rgb().0 > 128 { f32::NAN } else { 0.0 };
+        assert_eq!(vibrant(nan_for_red), Some((40, 40, 200)));
+        assert_eq!(vibrant(|_, _, _| f32::NAN), Some((200, 40, 40)));
+    }
+
+    #[test]
+    fn repeated_generation_selects_the_same_swatches() {
+        let image = hue_grid();
+        let selections = || -> Vec<_> {
+            PaletteBuilder::from_image(image.clone())
+                .generate()
+                .target_selections()
+                .map(|(_, swatch)| swatch)
+                .collect()
+        };
+
+        let first = selections();
+        for _ in 0..10 {
+            assert_eq!(selections(), first);
         }
     }
 
-    None
-}
+    #[test]
+    fn luma_alpha_images_use_their_opaque_region() {
+        use image::{GrayAlphaImage, LumaA};
 
-fn get_max_scored_swatch_for_target(
-    swatches: &[Swatch],
-    target: Target,
-    used_colors: &HashSet<(u8, u8, u8)>,
-) -> Option<Swatch> {
-    let dominant_swatch = swatches
-        .iter()
-        .copied()
-        .max_by_key(|swatch| swatch.population());
+        // a transparent white left half and an opaque mid-gray right half
+        let image = GrayAlphaImage::from_fn(32, 16, |x, _| {
+            if x < 16 {
+                LumaA([255, 0])
+            } else {
+                LumaA([128, 255])
+            }
+        });
+        let palette = PaletteBuilder::from_image(image).generate();
 
-    swatches
-        .iter()
-        .copied()
-        .filter(|swatch| should_be_scored_for_target(*swatch, target, used_colors))
-        .max_by(|lhs, rhs| {
-            generate_score(*lhs, dominant_swatch, target)
-                .partial_cmp(&generate_score(*rhs, dominant_swatch, target))
-                .unwrap()
-        })
-}
+        assert_eq!(palette.len(), 1);
+        let swatch = palette.swatches()[0];
+        assert_eq!(swatch.rgb(), (128, 128, 128));
+        assert_eq!(swatch.population(), 256);
+        assert!(palette.muted_swatch().is_some());
+    }
 
-fn should_be_scored_for_target(
-    swatch: Swatch,
-    target: Target,
-    used_colors: &HashSet<(u8, u8, u8)>,
-) -> bool {
-    let (_, s, l) = swatch.hsl();
+    #[test]
+    fn pure_red_fills_the_first_hue_bin() {
+        let hue_histogram =
+            |rgb| PaletteBuilder::from_image(stripes(&[(rgb, 10)])).hue_histogram(12);
+        let histogram = hue_histogram((255, 0, 0));
 
-    (target.minimum_saturation()..=target.maximum_saturation()).contains(&s)
-        && (target.minimum_lightness()..=target.maximum_lightness()).contains(&l)
-        && !used_colors.contains(&swatch.rgb())
-}
+        assert_eq!(histogram.len(), 12);
+        assert_eq!(histogram[0], 40);
+        assert_eq!(histogram[1..].iter().sum::<u32>(), 0);
 
-fn generate_score(swatch: Swatch, dominant_swatch: Option<Swatch>, target: Target) -> f32 {
-    let (_, saturation, lightness) = swatch.hsl();
+        // grays have no hue
+        assert!(hue_histogram((128, 128, 128)).iter().all(|&count| count == 0));
+        assert!(PaletteBuilder::from_image(blue_gradient()).hue_histogram(0).is_empty());
+    }
 
-    let max_population = if let Some(dominant_swatch) = dominant_swatch {
-        dominant_swatch.population() as f32
-    } else {
-        1.0
-    };
+    #[test]
+    fn single_pixel_swatches_are_pruned() {
+        let mut image = RgbImage::from_pixel(10, 10, Rgb([200, 40, 40]));
+        image.put_pixel(4, 4, Rgb([40, 40, 200]));
+        let colors = |population| {
+            sorted_colors(
+                PaletteBuilder::from_image(image.clone()).prune_swatches_below(population),
+            )
+        };
 
-    // calculate scores for saturation and luminance based on how close to the target values they
-    // are, weighted by the target
-    let saturation_score =
-        target.saturation_weight() * (1.0 - (saturation - target.target_saturation()).abs());
-    let lightness_score =
-        target.lightness_weight() * (1.0 - (lightness - target.target_lightness()).abs());
+        assert_eq!(colors(1), [(40, 40, 200), (200, 40, 40)]);
+        assert_eq!(colors(2), [(200, 40, 40)]);
+    }
 
-    // calculate score for the population based on how large it is compared to the dominant swatch,
-    // weighted by the target
-    let population_score =
-        target.population_weight() * (swatch.population() as f32 / max_population);
+    #[test]
+    fn output_filters_only_filter_swatches() {
+        let image = stripes(&[((200, 40, 40), 10), ((40, 40, 200), 10)]);
+        let builder =
+            || PaletteBuilder::from_image(image.clone()).add_output_filter(BlueBelow(128));
 
-    saturation_score + lightness_score + population_score
-}
+        // the blue is quantized, but its swatch is filtered out
+        assert_eq!(builder().histogram().len(), 2);
+        assert_eq!(sorted_colors(builder()), [(200, 40, 40)]);
 
-// thank you SO. https://stackoverflow.com/a/39147465
-fn rgb_to_hsl((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
-    let r = r as f32 / 255.0;
-    let g = g as f32 / 255.0;
-    let b = b as f32 / 255.0;
+        let builder =
+            || PaletteBuilder::from_image(image.clone()).add_input_filter(BlueBelow(128));
+        assert_eq!(builder().histogram().len(), 1);
+        assert_eq!(sorted_colors(builder()), [(200, 40, 40)]);
+    }
 
-    let max = r.max(g).max(b);
-    let min = r.min(g).min(b);
-    let c = max - min;
+    #[test]
+    fn rejected_swatches_are_kept_on_request() {
+        let image = stripes(&[((200, 40, 40), 10), ((40, 40, 200), 10)]);
+        let rejected = |keep| {
+            PaletteBuilder::from_image(image.clone())
+                .add_output_filter(BlueBelow(128))
+                .keep_rejected_swatches(keep)
+                .generate()
+                .rejected_swatches()
+                .to_vec()
+        };
 
-    let l = (max + min) / 2.0;
-    let (h, s) = if c == 0.0 {
-        (0.0, 0.0)
-    } else {
-        let s = c / (1.0 - (2.0 * l - 1.0).abs());
+        assert_eq!(rejected(true), [Swatch::new((40, 40, 200), 40)]);
+        assert!(rejected(false).is_empty());
+    }
 
-        let (segment, shift) = if max == r {
-            ((g - b) / c, if (g - b) / c < 0.0 { 6.0 } else { 0.0 })
-        } else if max == g {
-            ((b - r) / c, 2.0)
-        } else {
-            ((r - g) / c, 4.0)
+    #[test]
+    fn swatches_by_preset_name() {
+        let image = stripes(&[((248, 120, 120), 10), ((64, 48, 56), 10)]);
+        let palette = PaletteBuilder::from_image(image).generate();
+
+        assert_eq!(palette.light_vibrant_color(), Some((248, 120, 120)));
+        assert_eq!(palette.swatch_by_name("light_vibrant"), palette.light_vibrant_swatch());
+        assert_eq!(palette.swatch_by_name("dark_muted"), palette.dark_muted_swatch());
+        assert_eq!(palette.swatch_by_name("Light Vibrant"), None);
+    }
+
+    #[test]
+    fn named_colors_are_the_matched_presets() {
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+        let matched = PresetTarget::ALL
+            .into_iter()
+            .filter(|preset| palette.has_swatch_for_target(preset.target()))
+            .count();
+        let named_colors = palette.named_colors();
+
+        assert!(matched > 0 && matched < PresetTarget::ALL.len());
+        assert_eq!(named_colors.len(), matched);
+        assert!(named_colors
+            .iter()
+            .all(|(name, rgb)| palette.swatch_by_name(name).map(Swatch::rgb) == Some(*rgb)));
+    }
+
+    #[test]
+    fn target_selections_cover_every_target() {
+        let target = Target::around_hsl(0.0, 0.5, 0.5, 0.01);
+        let palette = PaletteBuilder::from_image(hue_grid()).add_target(target).generate();
+        let selections: Vec<_> = palette.target_selections().collect();
+
+        assert_eq!(selections.len(), 7);
+        assert!(selections
+            .iter()
+            .zip(palette.targets())
+            .all(|((target, swatch), expected)| {
+                *target == expected && *swatch == palette.get_swatch_for_target(*expected)
+            }));
+        assert_eq!(selections[6], (&target, None));
+    }
+
+    #[test]
+    fn blending_a_palette_with_itself_keeps_its_colors() {
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+
+        for t in [0.0, 0.25, 0.5, 1.0] {
+            let blended = palette.blend(&palette, t);
+
+            assert_eq!(blended.targets(), palette.targets());
+            assert!(blended.target_selections().eq(palette.target_selections()));
+        }
+    }
+
+    #[test]
+    fn saturation_boost_gives_a_small_accent_its_own_swatch() {
+        let accent = (200, 40, 40);
+        let image = stripes(&[
+            ((40, 40, 40), 50),
+            ((80, 80, 80), 50),
+            ((128, 128, 128), 50),
+            ((168, 168, 168), 50),
+            ((216, 216, 216), 50),
+            (accent, 1),
+        ]);
+        let has_accent = |boost| {
+            PaletteBuilder::from_image(image.clone())
+                .maximum_color_count(4)
+                .saturation_boost(boost)
+                .generate()
+                .swatches()
+                .iter()
+                .any(|swatch| swatch.rgb() == accent)
         };
 
-        (segment + shift, s)
-    };
+        assert!(!has_accent(0.0));
+        assert!(has_accent(100.0));
+    }
 
-    (h * 60.0, s, l)
+    #[test]
+    fn seeded_runs_are_equal() {
+        let image = hue_grid();
+        let palette = |seed| {
+            PaletteBuilder::from_image(image.clone())
+                .sample_count(Some(500))
+                .seed(seed)
+                .generate()
+        };
+
+        let first = palette(902);
+        assert_eq!(first.swatches(), palette(902).swatches());
+        assert!(first.target_selections().eq(palette(902).target_selections()));
+        assert_ne!(first, palette(903));
+    }
+
+    #[test]
+    fn luminance_sorted_swatches_start_with_the_darkest() {
+        let palette = PaletteBuilder::from_image(hue_grid()).generate();
+        let sorted = palette.swatches_sorted_by_luminance();
+        let luminances: Vec<_> = sorted.iter().map(|swatch| swatch.relative_luminance()).collect();
+
+        assert_eq!(sorted.len(), palette.len());
+        assert!(luminances.iter().all(|&luminance| luminances[0] <= luminance));
+        assert!(luminances.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn open_an_image_file() {
+        let path = std::env::temp_dir().join(format!("prominence-{}.png", std::process::id()));
+        hue_grid().save(&path).unwrap();
+
+        let opened = PaletteBuilder::open(&path).map(PaletteBuilder::generate);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(opened.unwrap(), PaletteBuilder::from_image(hue_grid()).generate());
+        assert!(PaletteBuilder::open(&path).is_err());
+    }
+
+    #[test]
+    fn normalized_region_is_scaled_to_the_image() {
+        let builder = || PaletteBuilder::from_image(RgbImage::new(100, 100));
+        let centered = Rect {
+            x: 25,
+            y: 25,
+            width: 50,
+            height: 50,
+        };
+
+        assert_eq!(builder().region_normalized(0.25, 0.25, 0.5, 0.5).region, Some(centered));
+        assert_eq!(
+            builder().region_normalized(-1.0, 0.5, 2.0, 0.75).region,
+            Some(Rect {
+                x: 0,
+                y: 50,
+                width: 100,
+                height: 50,
+            })
+        );
+    }
+
+    #[test]
+    fn region_past_the_edge_is_clamped() {
+        let image = stripes(&[((200, 40, 40), 8), ((40, 40, 200), 8)]);
+        let builder = PaletteBuilder::from_image(image).region(8, 2, 100, 100);
+
+        assert_eq!(
+            builder.region,
+            Some(Rect {
+                x: 8,
+                y: 2,
+                width: 8,
+                height: 2,
+            })
+        );
+        assert_eq!(sorted_colors(builder), vec![(40, 40, 200)]);
+    }
+
+    #[test]
+    fn hdr_image_is_tone_mapped() {
+        use image::Rgb32FImage;
+
+        // a bright red well above the nominal white next to a dim blue
+        let image = Rgb32FImage::from_fn(16, 4, |x, _| {
+            if x < 8 {
+                Rgb([6.0, 0.2, 0.2])
+            } else {
+                Rgb([0.0, 0.0, 0.5])
+            }
+        });
+        let palette =
+            PaletteBuilder::from_hdr_image(image.clone(), ToneMapping::Reinhard).generate();
+        let colors: Vec<_> = palette.swatches().iter().map(|swatch| swatch.rgb()).collect();
+
+        assert_eq!(colors.len(), 2);
+        assert!(colors.iter().any(|&(r, g, b)| r > 200 && g < 150 && b < 150));
+        assert!(colors.iter().any(|&(r, g, b)| r < 50 && g < 50 && b > 100));
+        let clamped = PaletteBuilder::from_hdr_image(image, ToneMapping::Clamp).generate();
+        assert!(clamped.swatches().iter().any(|swatch| swatch.rgb().0 == 248));
+    }
+
+    #[test]
+    fn first_available_falls_through_to_muted() {
+        let palette = PaletteBuilder::from_image(stripes(&[((104, 88, 136), 8)])).generate();
+        let muted = palette.muted_swatch();
+
+        assert!(palette.vibrant_swatch().is_none());
+        assert!(muted.is_some());
+        assert_eq!(palette.first_available(&[PresetTarget::Vibrant, PresetTarget::Muted]), muted);
+        assert_eq!(palette.first_available(&[PresetTarget::Vibrant]), None);
+        assert_eq!(palette.first_available(&[]), None);
+    }
+
+    #[test]
+    fn minimum_saturation_excludes_pale_grays() {
+        let vivid = (200, 40, 40);
+        let image = stripes(&[((136, 128, 136), 8), (vivid, 8)]);
+
+        assert_eq!(sorted_colors(PaletteBuilder::from_image(image.clone())).len(), 2);
+        assert_eq!(
+            sorted_colors(PaletteBuilder::from_image(image).minimum_saturation(0.2)),
+            vec![vivid]
+        );
+    }
+
+    #[test]
+    fn modal_color_is_the_most_common_exact_color() {
+        let background = (12, 34, 56);
+        let image = stripes(&[((13, 34, 56), 6), (background, 8), ((200, 40, 40), 6)]);
+
+        assert_eq!(PaletteBuilder::from_image(image.clone()).modal_color(), Some(background));
+        assert_eq!(
+            PaletteBuilder::from_image(image).region(14, 0, 6, 4).modal_color(),
+            Some((200, 40, 40))
+        );
+        assert_eq!(PaletteBuilder::from_image(RgbImage::new(0, 0)).modal_color(), None);
+    }
+
+    #[test]
+    fn mean_color_is_averaged_in_linear_light() {
+        let image = stripes(&[((0, 0, 0), 8), ((255, 255, 255), 8)]);
+        let palette = PaletteBuilder::from_image(image).use_default_filter(false).generate();
+
+        // the white is quantized into 248, so the mean is a little darker than the exact 188
+        let (r, g, b) = palette.mean_color().unwrap();
+        assert_eq!((r, r), (g, b));
+        assert!((180..=188).contains(&r));
+        assert_eq!(PaletteBuilder::from_image(RgbImage::new(0, 0)).generate().mean_color(), None);
+    }
+
+    #[test]
+    fn selected_swatch_has_the_highest_in_range_score() {
+        // a large gray background, a tiny fully vivid red and a larger, less saturated red
+        let image = stripes(&[((128, 128, 128), 1000), ((248, 8, 8), 1), ((200, 56, 56), 10)]);
+        let builders = [
+            PaletteBuilder::from_image(hue_grid()),
+            PaletteBuilder::from_image(hue_grid()).population_scale(PopulationScale::Log),
+            PaletteBuilder::from_image(image.clone()),
+            PaletteBuilder::from_image(image)
+                .population_reference(PopulationReference::InRangeDominant),
+        ];
+        let breakdowns: Vec<_> = builders
+            .into_iter()
+            .map(|builder| {
+                let palette = builder.generate();
+                let vibrant = palette.vibrant_swatch().unwrap();
+                let breakdown = palette.score_breakdown(Target::vibrant());
+
+                let (best, _, _) = breakdown
+                    .iter()
+                    .filter(|(_, _, in_range)| *in_range)
+                    .max_by(|(_, a, _), (_, b, _)| a.total_cmp(b))
+                    .unwrap();
+                assert_eq!(*best, vibrant);
+                assert_eq!(breakdown.len(), palette.len());
+                assert!(breakdown.iter().any(|(_, _, in_range)| !in_range));
+                breakdown
+            })
+            .collect();
+
+        // the breakdowns are scored with the builder's scoring rather than the default one
+        assert_ne!(breakdowns[0], breakdowns[1]);
+        assert_ne!(breakdowns[2], breakdowns[3]);
+    }
+
+    #[test]
+    fn population_references_select_different_swatches() {
+        // a large gray background, a tiny fully vivid red and a larger, less saturated red
+        let (vivid, larger) = ((248, 8, 8), (200, 56, 56));
+        let image = stripes(&[((128, 128, 128), 1000), (vivid, 1), (larger, 10)]);
+        let vibrant = |population_reference| {
+            PaletteBuilder::from_image(image.clone())
+                .population_reference(population_reference)
+                .generate()
+                .vibrant_swatch()
+                .map(Swatch::rgb)
+        };
+
+        assert_eq!(vibrant(PopulationReference::GlobalDominant), Some(vivid));
+        assert_eq!(vibrant(PopulationReference::InRangeDominant), Some(larger));
+    }
+
+    #[test]
+    fn palette_from_a_cropped_view() {
+        let image = stripes(&[((200, 40, 40), 8), ((40, 40, 200), 6), ((40, 200, 40), 2)]);
+        let view = image.view(8, 0, 8, 4);
+
+        let palette = PaletteBuilder::from_view(&*view).generate();
+        let copied = PaletteBuilder::from_image(view.to_image()).resize_image_area(None).generate();
+
+        assert_eq!(palette, copied);
+        let mut swatches: Vec<_> = palette
+            .swatches()
+            .iter()
+            .map(|swatch| (swatch.rgb(), swatch.population()))
+            .collect();
+        swatches.sort_unstable();
+        assert_eq!(swatches, vec![((40, 40, 200), 24), ((40, 200, 40), 8)]);
+    }
 }
@@ -0,0 +1,169 @@
+use std::time::Instant;
+
+use image::{Pixel, Rgb};
+
+use crate::{
+    filter::{FilterStage, StagedFilter},
+    pixel::QuantizablePixel,
+    swatch::Swatch,
+    QuantizationStats, Timings,
+};
+
+const LIGHTNESS_LEVELS: usize = 256;
+
+/// A quantizer for grayscale images that buckets pixels purely by their lightness.
+///
+/// Median cut has nothing to split along in a grayscale image since every color lies on the same
+/// line through the RGB cube, so instead the lightness range is divided into equally wide buckets
+/// and each non-empty bucket becomes a swatch. Fully transparent pixels are skipped, as are pixels
+/// the filters reject before quantizing.
+pub struct LightnessQuantizer<P>
+where
+    P: QuantizablePixel,
+{
+    pixels: Vec<P>,
+    // precomputed colors and their counts, quantized along with the pixels
+    weighted_colors: Vec<((u8, u8, u8), u32)>,
+    max_colors: usize,
+    filters: Vec<StagedFilter>,
+    weight_by_alpha: bool,
+}
+
+impl<P> LightnessQuantizer<P>
+where
+    P: QuantizablePixel,
+{
+    pub fn new(pixels: Vec<P>, max_colors: usize, filters: Vec<StagedFilter>) -> Self {
+        Self {
+            pixels,
+            weighted_colors: Vec::new(),
+            max_colors,
+            filters,
+            weight_by_alpha: false,
+        }
+    }
+
+    /// Add precomputed colors and their counts to quantize along with the pixels.
+    pub fn with_weighted_colors(self, weighted_colors: Vec<((u8, u8, u8), u32)>) -> Self {
+        Self {
+            weighted_colors,
+            ..self
+        }
+    }
+
+    /// Weight each pixel's count in the histogram by its alpha.
+    pub fn with_weight_by_alpha(self, weight_by_alpha: bool) -> Self {
+        Self {
+            weight_by_alpha,
+            ..self
+        }
+    }
+
+    /// Returns the histogram of lightness values as gray colors along with their counts, leaving
+    /// out lightness values no pixel has.
+    pub fn histogram(&self) -> Vec<((u8, u8, u8), u32)> {
+        self.build_histogram()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, count)| count > 0)
+            .map(|(luma, count)| ((luma as u8, luma as u8, luma as u8), count))
+            .collect()
+    }
+
+    /// Quantize the pixels into swatches, recording how long building the histogram and bucketing
+    /// it took along with statistics about the quantization. Returns the swatches allowed by the
+    /// filters along with the swatches the filters rejected.
+    pub fn get_quantized_colors(
+        self,
+        timings: &mut Timings,
+        stats: &mut QuantizationStats,
+    ) -> (Vec<Swatch>, Vec<Swatch>) {
+        let start = Instant::now();
+        let hist = self.build_histogram();
+        timings.histogram = start.elapsed();
+
+        let start = Instant::now();
+
+        // divide the lightness range into as many equally wide buckets as requested colors, and
+        // calculate the population and weighted sum of lightness values in each of them
+        let bucket_count = self.max_colors.clamp(1, LIGHTNESS_LEVELS);
+        let mut buckets = vec![(0u64, 0u64); bucket_count];
+        for (luma, count) in hist.into_iter().enumerate() {
+            let (pop, luma_sum) = &mut buckets[luma * bucket_count / LIGHTNESS_LEVELS];
+            *pop += count as u64;
+            *luma_sum += luma as u64 * count as u64;
+        }
+
+        stats.requested_colors = self.max_colors;
+        stats.histogram_colors = hist.iter().filter(|&&count| count > 0).count();
+        stats.quantized_colors = buckets.iter().filter(|(pop, _)| *pop > 0).count();
+        stats.terminated_early = stats.quantized_colors < self.max_colors;
+
+        // return the mean lightness of each non-empty bucket as a gray swatch, separating out
+        // unwanted colors
+        let swatches = buckets
+            .into_iter()
+            .filter(|(pop, _)| *pop > 0)
+            .map(|(pop, luma_sum)| {
+                let luma = (luma_sum as f64 / pop as f64).round() as u8;
+                Swatch::new((luma, luma, luma), pop.min(u32::MAX as u64) as u32)
+            })
+            .partition(|swatch| self.should_allow_color(FilterStage::Output, swatch.rgb()));
+
+        timings.split = start.elapsed();
+        swatches
+    }
+
+    /// Build a histogram of the pixels' lightness values, leaving out the pixels the filters
+    /// reject. When weighting by alpha, each pixel weighs its alpha value and each weighted color
+    /// weighs that of a fully opaque pixel per count.
+    fn build_histogram(&self) -> [u32; LIGHTNESS_LEVELS] {
+        // only compute the colors' HSL values if any filter is evaluated on them
+        let filter_input = self
+            .filters
+            .iter()
+            .any(|filter| filter.is_evaluated(FilterStage::Input));
+        let allow = |rgb| !filter_input || self.should_allow_color(FilterStage::Input, rgb);
+
+        let opaque_weight = if self.weight_by_alpha { u8::MAX as u64 } else { 1 };
+        let mut weights = [0u64; LIGHTNESS_LEVELS];
+        for pixel in self.pixels.iter() {
+            // fully transparent pixels, such as those outside the mask of a luma and alpha image,
+            // have no lightness to speak of
+            let alpha = pixel.alpha();
+            if alpha == 0 {
+                continue;
+            }
+
+            let rgb = pixel.rgb();
+            if !allow(rgb) {
+                continue;
+            }
+
+            let weight = if self.weight_by_alpha { alpha as u64 } else { 1 };
+            weights[luma(rgb) as usize] += weight;
+        }
+        for &(rgb, count) in self.weighted_colors.iter() {
+            if allow(rgb) {
+                weights[luma(rgb) as usize] += count as u64 * opaque_weight;
+            }
+        }
+
+        weights.map(|weight| {
+            let count = (weight + opaque_weight / 2) / opaque_weight;
+            count.min(u32::MAX as u64) as u32
+        })
+    }
+
+    fn should_allow_color(&self, stage: FilterStage, rgb: (u8, u8, u8)) -> bool {
+        let hsl = crate::rgb_to_hsl(rgb);
+        self.filters
+            .iter()
+            .all(|filter| filter.is_allowed(stage, rgb, hsl))
+    }
+}
+
+/// Returns the lightness of an 8-bit sRGB color the same way [`image::Pixel::to_luma`] computes it.
+fn luma((r, g, b): (u8, u8, u8)) -> u8 {
+    Rgb([r, g, b]).to_luma().0[0]
+}
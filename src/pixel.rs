@@ -0,0 +1,45 @@
+/// A pixel that can be quantized, giving its color in 8-bit sRGB along with its alpha.
+///
+/// This is implemented for every [`image::Pixel`] with 8-bit subpixels, which covers the common
+/// pixel types such as [`image::Rgb`], [`image::Rgba`], [`image::Luma`] and [`image::LumaA`].
+/// Pixels without an alpha channel are fully opaque.
+pub(crate) trait QuantizablePixel {
+    /// Returns the color of the pixel in 8-bit sRGB, leaving out its alpha.
+    fn rgb(&self) -> (u8, u8, u8);
+
+    /// Returns the alpha of the pixel, where 255 is fully opaque.
+    fn alpha(&self) -> u8;
+}
+
+impl<P> QuantizablePixel for P
+where
+    P: image::Pixel<Subpixel = u8>,
+{
+    fn rgb(&self) -> (u8, u8, u8) {
+        let rgb = self.to_rgb();
+        (rgb.0[0], rgb.0[1], rgb.0[2])
+    }
+
+    fn alpha(&self) -> u8 {
+        self.to_rgba().0[3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Luma, LumaA, Rgb, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn pixels_give_their_rgb_and_alpha() {
+        assert_eq!(Rgb([10u8, 20, 30]).rgb(), (10, 20, 30));
+        assert_eq!(Rgb([10u8, 20, 30]).alpha(), 255);
+        assert_eq!(Rgba([10u8, 20, 30, 40]).rgb(), (10, 20, 30));
+        assert_eq!(Rgba([10u8, 20, 30, 40]).alpha(), 40);
+        assert_eq!(Luma([50u8]).rgb(), (50, 50, 50));
+        assert_eq!(Luma([50u8]).alpha(), 255);
+        assert_eq!(LumaA([50u8, 60]).rgb(), (50, 50, 50));
+        assert_eq!(LumaA([50u8, 60]).alpha(), 60);
+    }
+}
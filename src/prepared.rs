@@ -0,0 +1,195 @@
+use image::{math::Rect, GenericImageView, ImageBuffer};
+
+use crate::{Palette, PaletteBuilder, DEFAULT_RESIZE_IMAGE_AREA};
+
+/// An image shrunk once ahead of time for generating palettes from several of its regions.
+///
+/// Generating a palette for a region with a [`PaletteBuilder`] shrinks the entire image every time.
+/// When many regions of the same image are queried, such as when a user drags a selection box over
+/// an image, a prepared image avoids the repeated work by shrinking the image only once. Regions
+/// are given in the coordinates of the original image and scaled to match the shrunk image the same
+/// way [`PaletteBuilder::region`] does.
+pub struct PreparedImage<P>
+where
+    P: image::Pixel<Subpixel = u8> + 'static + std::cmp::Eq + std::hash::Hash,
+{
+    image: ImageBuffer<P, Vec<u8>>,
+    original_dimensions: (u32, u32),
+    scale_ratio: f32,
+}
+
+impl<P> PreparedImage<P>
+where
+    P: image::Pixel<Subpixel = u8> + 'static + std::cmp::Eq + std::hash::Hash,
+{
+    /// Returns a new prepared image from a given image buffer, shrinking it to the default area in
+    /// the [`DEFAULT_RESIZE_IMAGE_AREA`] constant.
+    pub fn new(image: ImageBuffer<P, Vec<u8>>) -> Self {
+        Self::with_resize_area(image, Some(DEFAULT_RESIZE_IMAGE_AREA))
+    }
+
+    /// Returns a new prepared image from a given image buffer, shrinking it to the given area. See
+    /// [`PaletteBuilder::resize_image_area`].
+    pub fn with_resize_area(image: ImageBuffer<P, Vec<u8>>, resize_area: Option<u32>) -> Self {
+        let original_dimensions = image.dimensions();
+        let (image, scale_ratio) =
+            crate::scale_image_to_area(&image, resize_area).unwrap_or((image, 1.0));
+
+        Self {
+            image,
+            original_dimensions,
+            scale_ratio,
+        }
+    }
+
+    /// Returns the dimensions of the original image.
+    pub fn original_dimensions(&self) -> (u32, u32) {
+        self.original_dimensions
+    }
+
+    /// Returns the dimensions of the shrunk image.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    /// Returns the ratio the image was shrunk by. Both of its dimensions are scaled by the same
+    /// ratio, which preserves its aspect ratio, and regions are scaled by it too. An image that
+    /// wasn't shrunk has a ratio of 1.
+    ///
+    /// Since the shrunk dimensions are rounded up to whole pixels, they can be slightly larger than
+    /// the original dimensions multiplied by the ratio.
+    pub fn scale_ratio(&self) -> f32 {
+        self.scale_ratio
+    }
+
+    /// Scale a region given in the coordinates of the original image into the coordinates of the
+    /// shrunk image, the same way the region is scaled when generating a palette for it.
+    pub fn to_scaled_region(&self, region: Rect) -> Rect {
+        crate::scale_region(region, self.scale_ratio, self.image.dimensions())
+    }
+
+    /// Scale a region given in the coordinates of the shrunk image back into the coordinates of the
+    /// original image. The result covers at least the same area, so a region scaled with
+    /// [`PreparedImage::to_scaled_region`] maps back to the original region, with each edge off by
+    /// at most one pixel of the shrunk image due to rounding.
+    pub fn to_original_region(&self, region: Rect) -> Rect {
+        crate::scale_region(region, 1.0 / self.scale_ratio, self.original_dimensions)
+    }
+
+    /// Returns a new [`PaletteBuilder`] for a region of the image, given in the coordinates of the
+    /// original image.
+    ///
+    /// Only the pixels within the region are copied into the builder, and the builder doesn't
+    /// shrink them any further. Otherwise the builder can be configured like any other.
+    pub fn builder_for_region(&self, region: Rect) -> PaletteBuilder<P> {
+        let region = self.to_scaled_region(region);
+        let view = self.image.view(region.x, region.y, region.width, region.height);
+
+        PaletteBuilder::from_image(view.to_image()).resize_image_area(None)
+    }
+
+    /// Generate a new [`Palette`] with the default settings for a region of the image, given in the
+    /// coordinates of the original image.
+    pub fn palette_for_region(&self, region: Rect) -> Palette {
+        self.builder_for_region(region).generate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn region_palettes_only_see_the_region() {
+        // a red left half and a blue right half, large enough to be shrunk
+        let image = RgbImage::from_fn(400, 200, |x, _| {
+            if x < 200 {
+                Rgb([200, 30, 30])
+            } else {
+                Rgb([30, 30, 200])
+            }
+        });
+        let prepared = PreparedImage::new(image);
+
+        let palette = prepared.palette_for_region(Rect {
+            x: 200,
+            y: 0,
+            width: 200,
+            height: 200,
+        });
+
+        assert_eq!(palette.len(), 1);
+        let (r, g, b) = palette.swatches()[0].rgb();
+        assert!(b > 150 && r < 50 && g < 50);
+    }
+
+    #[test]
+    fn region_queries_are_independent() {
+        // a red top half and a blue bottom half, large enough to be shrunk
+        let image = RgbImage::from_fn(300, 300, |_, y| {
+            if y < 150 {
+                Rgb([200, 30, 30])
+            } else {
+                Rgb([30, 30, 200])
+            }
+        });
+        let prepared = PreparedImage::new(image.clone());
+        let (top, bottom) = (
+            Rect {
+                x: 0,
+                y: 0,
+                width: 300,
+                height: 150,
+            },
+            Rect {
+                x: 0,
+                y: 150,
+                width: 300,
+                height: 150,
+            },
+        );
+
+        let first = prepared.palette_for_region(top);
+        let second = prepared.palette_for_region(bottom);
+
+        assert_ne!(first, second);
+        assert_eq!(prepared.palette_for_region(top), first);
+        assert_eq!(prepared.dimensions(), (112, 112));
+
+        let unprepared = PaletteBuilder::from_image(image)
+            .region(bottom.x, bottom.y, bottom.width, bottom.height)
+            .generate();
+        assert_eq!(second, unprepared);
+    }
+
+    #[test]
+    fn scaled_regions_map_back_to_the_original() {
+        let prepared = PreparedImage::new(RgbImage::new(400, 200));
+        let ratio = prepared.scale_ratio();
+        let region = Rect {
+            x: 50,
+            y: 30,
+            width: 201,
+            height: 99,
+        };
+
+        let scaled = prepared.to_scaled_region(region);
+        let original = prepared.to_original_region(scaled);
+
+        // both axes are scaled by the same ratio
+        assert!(ratio < 1.0);
+        let (width, height) = prepared.dimensions();
+        assert_eq!((width, height), ((400.0 * ratio).ceil() as u32, (200.0 * ratio).ceil() as u32));
+        assert_eq!((scaled.x, scaled.y), ((50.0 * ratio) as u32, (30.0 * ratio) as u32));
+        // each edge is off by at most one pixel of the shrunk image
+        let max_error = (1.0 / ratio).ceil();
+        let error = |a: u32, b: u32| a.abs_diff(b) as f32;
+        assert!(error(original.x, region.x) <= max_error);
+        assert!(error(original.y, region.y) <= max_error);
+        assert!(error(original.x + original.width, region.x + region.width) <= max_error);
+        assert!(error(original.y + original.height, region.y + region.height) <= max_error);
+        assert!(original.x <= region.x && original.x + original.width >= region.x + region.width);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::swatch::Swatch;
+
+/// Generate a color scheme of `n` colors with evenly spaced hues around a base color.
+///
+/// The first color is the base color itself, and each following color has its HSL hue rotated a
+/// further `360 / n` degrees while keeping the base color's saturation and lightness, so for
+/// example 2 colors make a complementary pair and 3 colors make a triad. A palette's vibrant or
+/// dominant color makes for a natural base. An achromatic base color has no hue to rotate, so
+/// every color in its scheme is the same gray. See [`Swatch::rotate_hue`].
+pub fn harmonious_scheme(base: (u8, u8, u8), n: usize) -> Vec<(u8, u8, u8)> {
+    let base = Swatch::new(base, 0);
+    let step = 360.0 / n as f32;
+
+    (0..n)
+        .map(|i| base.rotate_hue(step * i as f32).rgb())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn three_colors_make_a_triad() {
+        assert_eq!(
+            harmonious_scheme((255, 0, 0), 3),
+            vec![(255, 0, 0), (0, 255, 0), (0, 0, 255)]
+        );
+
+        let scheme = harmonious_scheme((200, 40, 160), 3);
+        let hues: Vec<_> = scheme.iter().map(|&rgb| crate::rgb_to_hsl(rgb).0).collect();
+        assert_eq!(scheme[0], (200, 40, 160));
+        for (i, hue) in hues.iter().enumerate() {
+            let difference = (hue - hues[0] - 120.0 * i as f32).rem_euclid(360.0);
+            assert!(!(1.0..=359.0).contains(&difference), "{hues:?}");
+        }
+        assert!(harmonious_scheme((200, 40, 160), 0).is_empty());
+    }
+}
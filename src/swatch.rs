@@ -1,10 +1,30 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+use crate::color::ColorBlindness;
+
+const ACHROMATIC_MAX_SATURATION: f32 = 0.1;
+// the relative luminance at which black and white have equal contrast against a color
+const DARK_MAX_LUMINANCE: f32 = 0.179;
+
+/// A color in a palette along with how many pixels in the image it represents.
+///
+/// A swatch may carry a name, such as the name of the preset target it was selected for. The name
+/// is only a label; swatches compare equal by their color and population regardless of their names.
+#[derive(Debug, Clone, Copy)]
 pub struct Swatch {
     red: u8,
     blue: u8,
     green: u8,
     population: u32,
+    name: Option<&'static str>,
+}
+
+/// The serialized form of a [`Swatch`], with the color as a `#rrggbb` hex string.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SwatchRepr {
+    color: String,
+    population: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
 }
 
 impl Swatch {
@@ -14,18 +34,517 @@ impl Swatch {
             blue,
             green,
             population,
+            name: None,
         }
     }
 
+    /// Returns a copy of this swatch with the given name. With the `serde` feature, only the names
+    /// of the preset targets can be deserialized, and deserializing a swatch with any other name is
+    /// an error.
+    pub fn with_name(self, name: &'static str) -> Swatch {
+        Self {
+            name: Some(name),
+            ..self
+        }
+    }
+
+    /// Returns the name of the swatch, if it has one. Swatches selected for the preset targets are
+    /// named after the preset, such as "Light Vibrant".
+    pub fn name(self) -> Option<&'static str> {
+        self.name
+    }
+
     pub fn rgb(self) -> (u8, u8, u8) {
         (self.red, self.green, self.blue)
     }
 
+    /// Returns the swatch color along with the given alpha as a (red, green, blue, alpha) tuple.
+    pub fn rgba(self, alpha: u8) -> (u8, u8, u8, u8) {
+        (self.red, self.green, self.blue, alpha)
+    }
+
+    /// Returns the swatch color along with the given alpha as an [`image::Rgba`] pixel, for
+    /// example for drawing the color over an image with transparency.
+    pub fn to_rgba_pixel(self, alpha: u8) -> image::Rgba<u8> {
+        image::Rgba([self.red, self.green, self.blue, alpha])
+    }
+
+    /// Returns the swatch color in linear RGB as (red, green, blue) channels in the range 0 to 1.
+    ///
+    /// [`Swatch::rgb`] returns the gamma-encoded sRGB values, which is what images and CSS use.
+    /// These are the same channels with the sRGB transfer function undone, so they're proportional
+    /// to the amount of light, which is what shaders and lighting calculations generally expect.
+    /// For example, the sRGB channel value 128 is roughly 0.216 in linear light.
+    pub fn rgb_linear(self) -> (f32, f32, f32) {
+        let (r, g, b) = self.rgb();
+        (
+            crate::color::srgb_to_linear(r),
+            crate::color::srgb_to_linear(g),
+            crate::color::srgb_to_linear(b),
+        )
+    }
+
+    /// Returns `n` tints of the swatch color, getting evenly lighter towards white. The colors are
+    /// mixed with white in linear light, and neither the swatch color itself nor white is included,
+    /// so the last tint is the closest to white.
+    pub fn tints(self, n: usize) -> Vec<(u8, u8, u8)> {
+        self.mix_steps((1.0, 1.0, 1.0), n)
+    }
+
+    /// Returns `n` shades of the swatch color, getting evenly darker towards black. The colors are
+    /// mixed with black in linear light, and neither the swatch color itself nor black is included,
+    /// so the last shade is the closest to black.
+    pub fn shades(self, n: usize) -> Vec<(u8, u8, u8)> {
+        self.mix_steps((0.0, 0.0, 0.0), n)
+    }
+
+    /// Returns `n` colors evenly spaced between the swatch color and a given linear RGB color,
+    /// excluding both ends.
+    fn mix_steps(self, (to_r, to_g, to_b): (f32, f32, f32), n: usize) -> Vec<(u8, u8, u8)> {
+        let (r, g, b) = self.rgb_linear();
+        let mix = |from: f32, to: f32, t: f32| crate::color::linear_to_srgb(from + (to - from) * t);
+
+        (1..=n)
+            .map(|step| {
+                let t = step as f32 / (n + 1) as f32;
+                (mix(r, to_r, t), mix(g, to_g, t), mix(b, to_b, t))
+            })
+            .collect()
+    }
+
+    /// Returns whether this swatch has the same color as another, regardless of their populations
+    /// and names.
+    pub fn same_color(self, other: Swatch) -> bool {
+        self.rgb() == other.rgb()
+    }
+
+    /// Returns a new swatch from an 8-bit sRGB color of the [`palette`] crate. The inverse is
+    /// available as a `From<Swatch>` conversion into [`palette::Srgb<u8>`].
+    #[cfg(feature = "palette")]
+    pub fn from_srgb(color: palette::Srgb<u8>, population: u32) -> Swatch {
+        Swatch::new(color.into_components(), population)
+    }
+
+    /// Returns a new swatch from a color packed into an integer as `0x00RRGGBB`, as returned by
+    /// [`Swatch::to_u32`]. The highest byte is ignored.
+    pub fn from_u32(packed: u32, population: u32) -> Swatch {
+        let [_, red, green, blue] = packed.to_be_bytes();
+        Swatch::new((red, green, blue), population)
+    }
+
+    /// Returns the swatch color packed into an integer as `0x00RRGGBB`. The population isn't
+    /// included.
+    pub fn to_u32(self) -> u32 {
+        u32::from_be_bytes([0, self.red, self.green, self.blue])
+    }
+
     pub fn hsl(self) -> (f32, f32, f32) {
         crate::rgb_to_hsl(self.rgb())
     }
 
+    /// Returns a copy of this swatch with its hue rotated by the given amount of degrees, keeping
+    /// its HSL saturation and lightness, population and name. The hue wraps around, so negative
+    /// amounts and amounts past 360 degrees work as expected. For example, rotating by 180 degrees
+    /// gives the complementary color.
+    ///
+    /// The color is converted to HSL and back, so the result may be off by one from the exact
+    /// color in each channel. Achromatic swatches have no hue to rotate and stay the same.
+    pub fn rotate_hue(self, degrees: f32) -> Swatch {
+        let (h, s, l) = self.hsl();
+        let (red, green, blue) = crate::hsl_to_rgb((h + degrees, s, l));
+
+        Self {
+            red,
+            green,
+            blue,
+            ..self
+        }
+    }
+
+    /// Returns the swatch color in OKLch as a (lightness, chroma, hue) tuple, with the hue in
+    /// degrees.
+    pub fn oklch(self) -> (f32, f32, f32) {
+        crate::color::rgb_to_oklch(self.rgb())
+    }
+
     pub fn population(self) -> u32 {
         self.population
     }
+
+    /// Returns the relative luminance of the swatch color as defined by WCAG, in the range 0 to 1.
+    pub fn relative_luminance(self) -> f32 {
+        crate::color::relative_luminance(self.rgb())
+    }
+
+    /// Returns the perceived brightness of the swatch color in the HSP color model, in the range 0
+    /// to 1.
+    ///
+    /// The brightness is `sqrt(0.299 * r² + 0.587 * g² + 0.114 * b²)` computed directly on the
+    /// sRGB channels. Unlike [`Swatch::relative_luminance`], which weighs the channels in linear light,
+    /// this works on the gamma-encoded values, so it grows more evenly across the sRGB range and
+    /// mid-tones read brighter than they do in relative luminance.
+    pub fn perceived_brightness(self) -> f32 {
+        let (r, g, b) = self.rgb();
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+
+        (0.299 * r * r + 0.587 * g * g + 0.114 * b * b).sqrt()
+    }
+
+    /// Returns whether the swatch is a dark color, meaning white has better contrast against it
+    /// than black. This is the case when its relative luminance is below roughly 0.18.
+    pub fn is_dark(self) -> bool {
+        self.relative_luminance() < DARK_MAX_LUMINANCE
+    }
+
+    /// Returns whether the swatch is a light color, meaning black has better contrast against it
+    /// than white. This is the opposite of [`Swatch::is_dark`].
+    pub fn is_light(self) -> bool {
+        !self.is_dark()
+    }
+
+    /// Returns the swatch color as a `#rrggbb` hex string.
+    pub fn hex(self) -> String {
+        let (r, g, b) = self.rgb();
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+
+    /// Returns the swatch color as a CSS `rgb()` color, such as `rgb(255, 0, 0)`.
+    pub fn css_rgb(self) -> String {
+        let (r, g, b) = self.rgb();
+        format!("rgb({r}, {g}, {b})")
+    }
+
+    /// Returns the swatch color as a CSS `hsl()` color, such as `hsl(0, 100%, 50%)`. Each
+    /// component is rounded to the nearest integer.
+    pub fn css_hsl(self) -> String {
+        let (h, s, l) = self.hsl();
+        let h = h.round() as u32 % 360;
+        let s = (s * 100.0).round() as u32;
+        let l = (l * 100.0).round() as u32;
+
+        format!("hsl({h}, {s}%, {l}%)")
+    }
+
+    /// Returns the name of the CSS named color closest to the swatch color, such as `"red"` or
+    /// `"aliceblue"`.
+    ///
+    /// The closest color is the one with the smallest Euclidean distance in CIELAB. For colors with
+    /// several names, such as `aqua` and `cyan`, the alphabetically first name is returned.
+    pub fn nearest_css_name(self) -> &'static str {
+        let lab = crate::color::rgb_to_lab(self.rgb());
+        let distance = |rgb| crate::color::lab_distance(lab, crate::color::rgb_to_lab(rgb));
+
+        crate::css::CSS_NAMED_COLORS
+            .iter()
+            .map(|&(name, rgb)| (name, distance(rgb)))
+            .min_by(|(_, lhs), (_, rhs)| lhs.total_cmp(rhs))
+            .map(|(name, _)| name)
+            .expect("the named color table is not empty")
+    }
+
+    /// Returns how the swatch color looks to someone with a given color vision deficiency.
+    ///
+    /// The simulation is done in linear RGB at full severity of the deficiency. This is useful for
+    /// previewing how a palette appears to color-blind users.
+    pub fn simulate(self, kind: ColorBlindness) -> (u8, u8, u8) {
+        crate::color::simulate_color_blindness(self.rgb(), kind)
+    }
+
+    /// Returns an estimate of the correlated color temperature of the swatch color in kelvin.
+    ///
+    /// The temperature is estimated from the color's CIE xy chromaticity with McCamy's
+    /// approximation, so a warm white reads roughly 3000 K and the sRGB white point roughly 6500 K.
+    /// Color temperature is only meaningful for colors close to white light, so `None` is returned
+    /// for black, for saturated colors far from the Planckian locus and for temperatures outside
+    /// 1000 to 15000 K.
+    pub fn color_temperature_kelvin(self) -> Option<f32> {
+        crate::color::correlated_color_temperature(self.rgb())
+    }
+
+    /// Returns whether the swatch is a warm color, meaning its hue lies in the red-yellow arc
+    /// between 300 and 60 degrees. Achromatic swatches are never warm.
+    pub fn is_warm(self) -> bool {
+        let (h, s, _) = self.hsl();
+        s > ACHROMATIC_MAX_SATURATION && !(60.0..300.0).contains(&h)
+    }
+
+    /// Returns 1 for a warm swatch, -1 for a cool swatch and 0 for an achromatic swatch.
+    pub(crate) fn temperature(self) -> f32 {
+        let (_, s, _) = self.hsl();
+
+        if s <= ACHROMATIC_MAX_SATURATION {
+            0.0
+        } else if self.is_warm() {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+impl Eq for Swatch {}
+impl PartialEq for Swatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.rgb() == other.rgb() && self.population == other.population
+    }
+}
+
+#[cfg(feature = "palette")]
+impl From<Swatch> for palette::Srgb<u8> {
+    fn from(swatch: Swatch) -> Self {
+        let (red, green, blue) = swatch.rgb();
+        palette::Srgb::new(red, green, blue)
+    }
+}
+
+// serialized through SwatchRepr. these can't be derived with serde's `into` and `try_from` since
+// the static name would make the derived deserializer require a static lifetime
+#[cfg(feature = "serde")]
+impl serde::Serialize for Swatch {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SwatchRepr::from(*self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Swatch {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SwatchRepr::deserialize(deserializer)?;
+        Swatch::try_from(repr).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Swatch> for SwatchRepr {
+    fn from(swatch: Swatch) -> Self {
+        Self {
+            color: swatch.hex(),
+            population: swatch.population,
+            name: swatch.name.map(String::from),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SwatchRepr> for Swatch {
+    type Error = String;
+
+    fn try_from(repr: SwatchRepr) -> Result<Self, Self::Error> {
+        let rgb = crate::color::parse_hex(&repr.color)
+            .ok_or_else(|| format!("invalid hex color: {}", repr.color))?;
+        let swatch = Swatch::new(rgb, repr.population);
+
+        let Some(name) = repr.name else {
+            return Ok(swatch);
+        };
+
+        // names have to be static, so only the names of the preset targets can be restored.
+        // rather than silently dropping any other name, refuse to deserialize it
+        crate::PresetTarget::ALL
+            .into_iter()
+            .map(|preset| preset.name())
+            .find(|&preset_name| preset_name == name)
+            .map(|name| swatch.with_name(name))
+            .ok_or_else(|| format!("unknown swatch name: {name}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    fn repr(name: Option<&str>) -> SwatchRepr {
+        SwatchRepr {
+            color: String::from("#336699"),
+            population: 42,
+            name: name.map(String::from),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn preset_names_round_trip() {
+        let swatch = Swatch::new((0x33, 0x66, 0x99), 42).with_name("Light Vibrant");
+        let restored = Swatch::try_from(SwatchRepr::from(swatch)).unwrap();
+
+        assert_eq!(restored, swatch);
+        assert_eq!(restored.name(), Some("Light Vibrant"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn unnamed_swatches_round_trip() {
+        let restored = Swatch::try_from(repr(None)).unwrap();
+
+        assert_eq!(restored, Swatch::new((0x33, 0x66, 0x99), 42));
+        assert_eq!(restored.name(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn unknown_names_are_an_error() {
+        assert!(Swatch::try_from(repr(Some("Accent"))).is_err());
+    }
+
+    #[test]
+    fn orange_is_warm_and_blue_is_cool() {
+        let orange = Swatch::new((255, 140, 0), 1);
+        let blue = Swatch::new((30, 60, 220), 1);
+        let gray = Swatch::new((128, 128, 128), 1);
+
+        assert!(orange.is_warm());
+        assert_eq!(orange.temperature(), 1.0);
+        assert!(!blue.is_warm());
+        assert_eq!(blue.temperature(), -1.0);
+        assert!(!gray.is_warm());
+        assert_eq!(gray.temperature(), 0.0);
+    }
+
+    #[test]
+    fn pure_red_as_css() {
+        let red = Swatch::new((255, 0, 0), 1);
+
+        assert_eq!(red.css_rgb(), "rgb(255, 0, 0)");
+        assert_eq!(red.css_hsl(), "hsl(0, 100%, 50%)");
+    }
+
+    #[test]
+    fn near_black_is_dark_and_near_white_is_light() {
+        let black = Swatch::new((12, 10, 14), 1);
+        let white = Swatch::new((245, 245, 240), 1);
+
+        assert!(black.is_dark() && !black.is_light());
+        assert!(white.is_light() && !white.is_dark());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn swatches_serialize_their_color_as_hex() {
+        let swatch = Swatch::new((0x33, 0x66, 0x99), 42);
+        let serialized = ron::to_string(&swatch).unwrap();
+
+        assert!(serialized.contains("\"#336699\""));
+        assert_eq!(ron::from_str::<Swatch>(&serialized).unwrap(), swatch);
+    }
+
+    #[test]
+    fn names_are_labels_only() {
+        let swatch = Swatch::new((200, 40, 40), 10);
+        let named = swatch.with_name("Accent");
+
+        assert_eq!(swatch.name(), None);
+        assert_eq!(named.name(), Some("Accent"));
+        assert_eq!(named, swatch);
+    }
+
+    #[test]
+    fn nearest_css_names() {
+        assert_eq!(Swatch::new((255, 0, 0), 1).nearest_css_name(), "red");
+        assert_eq!(Swatch::new((240, 248, 255), 1).nearest_css_name(), "aliceblue");
+        // aqua and cyan are the same color
+        assert_eq!(Swatch::new((0, 250, 250), 1).nearest_css_name(), "aqua");
+    }
+
+    #[test]
+    fn packed_colors_round_trip() {
+        let swatch = Swatch::new((0x12, 0x34, 0x56), 7);
+
+        assert_eq!(swatch.to_u32(), 0x0012_3456);
+        assert_eq!(Swatch::from_u32(swatch.to_u32(), 7), swatch);
+        assert_eq!(Swatch::from_u32(0xff12_3456, 7).rgb(), (0x12, 0x34, 0x56));
+    }
+
+    #[test]
+    fn deuteranopia_shifts_green_towards_yellow() {
+        let (r, g, b) = Swatch::new((0, 255, 0), 1).simulate(ColorBlindness::Deuteranopia);
+
+        assert!(r > 128 && g > 128 && b < 96);
+        // blue is mostly unaffected
+        let (_, _, b) = Swatch::new((0, 0, 255), 1).simulate(ColorBlindness::Deuteranopia);
+        assert!(b > 240);
+    }
+
+    #[test]
+    fn yellow_is_perceived_brighter_than_blue() {
+        let yellow = Swatch::new((255, 255, 0), 1).perceived_brightness();
+        let blue = Swatch::new((0, 0, 255), 1).perceived_brightness();
+
+        assert!(yellow > blue);
+        assert!((Swatch::new((255, 255, 255), 1).perceived_brightness() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn whites_have_color_temperatures() {
+        let kelvin = |rgb| Swatch::new(rgb, 1).color_temperature_kelvin().unwrap();
+
+        assert!((kelvin((255, 177, 110)) - 3000.0).abs() < 300.0);
+        assert!((kelvin((255, 255, 255)) - 6500.0).abs() < 300.0);
+        assert_eq!(Swatch::new((0, 0, 0), 1).color_temperature_kelvin(), None);
+    }
+
+    #[test]
+    fn same_color_ignores_population_and_name() {
+        let swatch = Swatch::new((200, 40, 40), 10);
+
+        assert!(swatch.same_color(Swatch::new((200, 40, 40), 20).with_name("Vibrant")));
+        assert!(!swatch.same_color(Swatch::new((200, 40, 48), 10)));
+        assert_ne!(swatch, Swatch::new((200, 40, 40), 20));
+    }
+
+    #[cfg(feature = "palette")]
+    #[test]
+    fn srgb_round_trip() {
+        let swatch = Swatch::new((51, 102, 153), 42);
+        let color = palette::Srgb::<u8>::from(swatch);
+
+        assert_eq!(color, palette::Srgb::new(51, 102, 153));
+        assert_eq!(Swatch::from_srgb(color, 42), swatch);
+    }
+
+    #[test]
+    fn mid_gray_linearizes() {
+        let (r, g, b) = Swatch::new((128, 128, 128), 1).rgb_linear();
+
+        assert!((r - 0.2158).abs() < 0.001);
+        assert_eq!((r, r), (g, b));
+        assert_eq!(Swatch::new((0, 0, 0), 1).rgb_linear(), (0.0, 0.0, 0.0));
+        assert_eq!(Swatch::new((255, 255, 255), 1).rgb_linear(), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn red_rotated_by_a_third_is_green() {
+        let red = Swatch::new((255, 0, 0), 7);
+
+        assert_eq!(red.rotate_hue(120.0).rgb(), (0, 255, 0));
+        assert_eq!(red.rotate_hue(120.0).population(), 7);
+        assert_eq!(red.rotate_hue(-240.0).rgb(), (0, 255, 0));
+        assert_eq!(red.rotate_hue(360.0), red);
+    }
+
+    #[test]
+    fn tints_and_shades_approach_white_and_black() {
+        let swatch = Swatch::new((200, 40, 40), 1);
+        let tints = swatch.tints(10);
+        let shades = swatch.shades(10);
+
+        assert_eq!((tints.len(), shades.len()), (10, 10));
+        let (r, g, b) = tints[9];
+        assert!(r > 240 && g > 230 && b > 230);
+        assert!(r >= g && g == b);
+        let (r, g, b) = shades[9];
+        assert!(r < 80 && g < 20 && b < 20);
+        assert!(tints.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert!(shades.windows(2).all(|pair| pair[0].0 > pair[1].0));
+    }
+
+    #[test]
+    fn alpha_is_carried_through() {
+        let swatch = Swatch::new((51, 102, 153), 42);
+
+        for alpha in [0, 51, 255] {
+            assert_eq!(swatch.rgba(alpha), (51, 102, 153, alpha));
+            assert_eq!(swatch.to_rgba_pixel(alpha), image::Rgba([51, 102, 153, alpha]));
+        }
+    }
 }
@@ -1,5 +1,7 @@
 use std::hash::Hash;
 
+use crate::swatch::Swatch;
+
 const WEIGHT_SATURATION: f32 = 0.24;
 const WEIGHT_LUMA: f32 = 0.52;
 const WEIGHT_POPULATION: f32 = 0.24;
@@ -31,6 +33,33 @@ pub struct Target {
     // sat, luma, pop
     weights: (f32, f32, f32),
     is_exclusive: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    space: TargetSpace,
+}
+
+/// The preset targets included in every palette by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PresetTarget {
+    LightVibrant,
+    Vibrant,
+    DarkVibrant,
+    LightMuted,
+    Muted,
+    DarkMuted,
+}
+
+/// The color space a [`Target`]'s saturation and lightness values are defined in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TargetSpace {
+    /// The saturation and lightness are HSL saturation and lightness, both in the range 0 to 1.
+    #[default]
+    Hsl,
+    /// The saturation is OKLch chroma and the lightness is OKLch lightness. The lightness is in the
+    /// range 0 to 1, while the chroma of colors within the sRGB gamut is roughly in the range 0 to
+    /// 0.32.
+    OkLch,
 }
 
 impl Target {
@@ -106,6 +135,50 @@ impl Target {
             lightness_targets: (0.0, 0.5, 1.0),
             weights: (WEIGHT_SATURATION, WEIGHT_LUMA, WEIGHT_POPULATION),
             is_exclusive: true,
+            space: TargetSpace::Hsl,
+        }
+    }
+
+    /// Returns a new target centered on a given HSL color, accepting saturation and lightness
+    /// values within the given tolerance of the color's. The ranges are clamped between 0 and 1.
+    ///
+    /// The hue is accepted for convenience, but it doesn't affect the target since hue isn't part
+    /// of scoring swatches for targets.
+    pub fn around_hsl(_hue: f32, saturation: f32, lightness: f32, tolerance: f32) -> Self {
+        Self {
+            saturation_targets: (
+                (saturation - tolerance).max(0.0),
+                saturation,
+                (saturation + tolerance).min(1.0),
+            ),
+            lightness_targets: (
+                (lightness - tolerance).max(0.0),
+                lightness,
+                (lightness + tolerance).min(1.0),
+            ),
+            ..Target::new()
+        }
+    }
+
+    /// Returns a new target with its acceptable region defined in OKLch. The chroma and lightness
+    /// are given as (minimum, target, maximum) tuples. See [`TargetSpace::OkLch`].
+    pub fn oklch(chroma_targets: (f32, f32, f32), lightness_targets: (f32, f32, f32)) -> Self {
+        Self {
+            saturation_targets: chroma_targets,
+            lightness_targets,
+            space: TargetSpace::OkLch,
+            ..Target::new()
+        }
+    }
+
+    /// Returns a copy of this target with the given saturation, lightness and population weights.
+    /// The weights are normalized to sum up to 1 when scoring swatches. A weight of 0 leaves its
+    /// score out entirely, so for example a population weight of 0 matches swatches by their color
+    /// only.
+    pub fn with_weights(self, saturation: f32, lightness: f32, population: f32) -> Self {
+        Self {
+            weights: (saturation, lightness, population),
+            ..self
         }
     }
 
@@ -113,6 +186,34 @@ impl Target {
         self.name
     }
 
+    /// Returns the preset this target is, if any.
+    pub(crate) fn preset(self) -> Option<PresetTarget> {
+        PresetTarget::ALL
+            .into_iter()
+            .find(|preset| preset.target().id() == self.id())
+    }
+
+    /// Returns a copy of this target with its saturation and lightness ranges widened by the given
+    /// amount in both directions, clamped between 0 and 1.
+    pub(crate) fn relaxed(self, amount: f32) -> Target {
+        let (min_saturation, target_saturation, max_saturation) = self.saturation_targets;
+        let (min_lightness, target_lightness, max_lightness) = self.lightness_targets;
+
+        Target {
+            saturation_targets: (
+                (min_saturation - amount).max(0.0),
+                target_saturation,
+                (max_saturation + amount).min(1.0),
+            ),
+            lightness_targets: (
+                (min_lightness - amount).max(0.0),
+                target_lightness,
+                (max_lightness + amount).min(1.0),
+            ),
+            ..self
+        }
+    }
+
     pub(crate) fn normalize_weights(&mut self) {
         let weights_sum = self.weights.0 + self.weights.1 + self.weights.2;
 
@@ -162,6 +263,10 @@ impl Target {
     pub fn is_exclusive(self) -> bool {
         self.is_exclusive
     }
+
+    pub fn space(self) -> TargetSpace {
+        self.space
+    }
 }
 
 impl Default for Target {
@@ -182,3 +287,99 @@ impl Hash for Target {
         self.name.hash(state);
     }
 }
+
+impl TargetSpace {
+    /// Returns a swatch's saturation and lightness in this color space.
+    pub(crate) fn saturation_and_lightness(self, swatch: Swatch) -> (f32, f32) {
+        match self {
+            TargetSpace::Hsl => {
+                let (_, s, l) = swatch.hsl();
+                (s, l)
+            }
+            TargetSpace::OkLch => {
+                let (l, c, _) = swatch.oklch();
+                (c, l)
+            }
+        }
+    }
+}
+
+impl PresetTarget {
+    pub(crate) const ALL: [PresetTarget; 6] = [
+        PresetTarget::LightVibrant,
+        PresetTarget::Vibrant,
+        PresetTarget::DarkVibrant,
+        PresetTarget::LightMuted,
+        PresetTarget::Muted,
+        PresetTarget::DarkMuted,
+    ];
+
+    /// Returns the name of this preset as an identifier, such as "light_vibrant".
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            PresetTarget::LightVibrant => "light_vibrant",
+            PresetTarget::Vibrant => "vibrant",
+            PresetTarget::DarkVibrant => "dark_vibrant",
+            PresetTarget::LightMuted => "light_muted",
+            PresetTarget::Muted => "muted",
+            PresetTarget::DarkMuted => "dark_muted",
+        }
+    }
+
+    /// Returns the preset with the given identifier name, such as "light_vibrant", if there is one.
+    pub(crate) fn from_key(key: &str) -> Option<PresetTarget> {
+        PresetTarget::ALL
+            .into_iter()
+            .find(|preset| preset.key() == key)
+    }
+
+    /// Returns the human-readable name of this preset, such as "Light Vibrant".
+    pub fn name(self) -> &'static str {
+        match self {
+            PresetTarget::LightVibrant => "Light Vibrant",
+            PresetTarget::Vibrant => "Vibrant",
+            PresetTarget::DarkVibrant => "Dark Vibrant",
+            PresetTarget::LightMuted => "Light Muted",
+            PresetTarget::Muted => "Muted",
+            PresetTarget::DarkMuted => "Dark Muted",
+        }
+    }
+
+    /// Returns the [`Target`] corresponding to this preset.
+    pub fn target(self) -> Target {
+        match self {
+            PresetTarget::LightVibrant => Target::light_vibrant(),
+            PresetTarget::Vibrant => Target::vibrant(),
+            PresetTarget::DarkVibrant => Target::dark_vibrant(),
+            PresetTarget::LightMuted => Target::light_muted(),
+            PresetTarget::Muted => Target::muted(),
+            PresetTarget::DarkMuted => Target::dark_muted(),
+        }
+    }
+}
+
+impl From<PresetTarget> for Target {
+    fn from(preset: PresetTarget) -> Self {
+        preset.target()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn around_hsl_targets_the_given_color() {
+        let target = Target::around_hsl(200.0, 0.7, 0.4, 0.2);
+
+        assert_eq!(target.target_saturation(), 0.7);
+        assert_eq!(target.target_lightness(), 0.4);
+        assert!((target.minimum_saturation() - 0.5).abs() < 1e-6);
+        assert!((target.maximum_lightness() - 0.6).abs() < 1e-6);
+
+        // the ranges are clamped
+        let target = Target::around_hsl(0.0, 0.9, 0.1, 0.2);
+        assert_eq!(target.maximum_saturation(), 1.0);
+        assert_eq!(target.minimum_lightness(), 0.0);
+    }
+}